@@ -0,0 +1,79 @@
+// Compares recycling entities through EntityPool against plain spawn/despawn.
+// Run with `cargo bench --bench pool`.
+use bevy::prelude::*;
+use std::time::Instant;
+
+#[path = "../src/pool.rs"]
+mod pool;
+
+use pool::EntityPool;
+
+const BATCH: usize = 5_000;
+const FRAMES: usize = 200;
+
+#[derive(Component)]
+struct Bullet;
+
+fn bullet_bundle() -> impl Bundle {
+    (Bullet, Transform::default(), GlobalTransform::default(), Visibility::default())
+}
+
+fn pooled_cycle(
+    mut commands: Commands,
+    mut pool: ResMut<EntityPool<Bullet>>,
+    query: Query<Entity, With<Bullet>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).remove::<Bullet>();
+        pool.release(&mut commands, entity);
+    }
+
+    for _ in 0..BATCH {
+        pool.spawn(&mut commands, bullet_bundle());
+    }
+}
+
+fn despawn_cycle(mut commands: Commands, query: Query<Entity, With<Bullet>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    for _ in 0..BATCH {
+        commands.spawn(bullet_bundle());
+    }
+}
+
+fn run(name: &str, mut app: App) {
+    // warm up so the first allocation of every archetype isn't measured
+    app.update();
+
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        app.update();
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    // every frame spawns one batch and retires the one before it
+    let entities = (BATCH * FRAMES) as f64;
+    println!(
+        "{:<10} {:>8.2} ms/frame {:>12.0} spawns+despawns/sec ({} entities alive)",
+        name,
+        elapsed * 1000. / FRAMES as f64,
+        entities / elapsed,
+        app.world.entities().len(),
+    );
+}
+
+fn main() {
+    let mut pooled = App::new();
+    pooled
+        .add_plugins(MinimalPlugins)
+        .init_resource::<EntityPool<Bullet>>()
+        .add_system(pooled_cycle);
+
+    let mut despawned = App::new();
+    despawned.add_plugins(MinimalPlugins).add_system(despawn_cycle);
+
+    run("pooled", pooled);
+    run("despawn", despawned);
+}
@@ -0,0 +1,642 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::{PI, TAU};
+
+use crate::boss::Boss;
+use crate::commons::{load_data, AppState, GameRng};
+use crate::debug::timed;
+use crate::enemy::{release_enemy, Chilled, Elevation, Enemy, EnemyKilled, EnemyKind, EnemyState};
+use crate::loot::LootTable;
+use crate::particles::{ParticleBurst, ParticlePreset};
+use crate::player::Projectile;
+use crate::pool::EntityPool;
+use crate::sound::{Sound, SoundEvent};
+use crate::wall::Wall;
+
+pub struct AiPlugin;
+
+impl Plugin for AiPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Archetypes::load()).add_system_set(
+            SystemSet::on_update(AppState::Playing)
+                .with_system(attach_brains)
+                .with_system(timed(think))
+                .with_system(timed(steer).after(think))
+                .with_system(update_elevation.after(steer)),
+        );
+    }
+}
+
+pub const ARCHETYPES_FILE: &str = "archetypes.ron";
+const BUILT_IN_ARCHETYPES: &str = include_str!("../assets/archetypes.ron");
+
+// One piece of how an enemy moves or decides. Archetypes stack several of
+// them and each one adds its part to the enemy's movement or transitions.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum Behavior {
+    // march towards the wall
+    Advance,
+    // weave up and down across lanes while walking
+    ZigZag { amplitude: f32, period: f32 },
+    // run back the way it came once health drops below this fraction
+    Flee { below: f32 },
+    // hold at `hold_x` until `size` enemies are within `radius`, or patience runs out
+    WaitForGroup { size: usize, radius: f32, hold_x: f32, patience: f32 },
+    // sidestep projectiles that come within `radius`
+    Dodge { radius: f32, speed: f32 },
+}
+
+// How an enemy gets past the wall
+#[derive(Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Movement {
+    // walks up to the wall and attacks it
+    #[default]
+    Ground,
+    // flies over the wall and heads for the wizard
+    Flying,
+    // tunnels under part of the field where fire can't reach it
+    Burrowing,
+    // jumps the wall once it's badly damaged
+    Leaping,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Archetype {
+    #[serde(default)]
+    pub movement: Movement,
+    pub behaviors: Vec<Behavior>,
+    #[serde(default)]
+    pub loot: LootTable,
+}
+
+#[derive(Resource, Serialize, Deserialize)]
+pub struct Archetypes {
+    pub grunt: Archetype,
+    pub boss: Archetype,
+    pub flyer: Archetype,
+    pub burrower: Archetype,
+    pub leaper: Archetype,
+}
+
+impl Archetypes {
+    pub fn load() -> Self {
+        load_data(ARCHETYPES_FILE, BUILT_IN_ARCHETYPES)
+    }
+
+    pub fn get(&self, kind: EnemyKind) -> &Archetype {
+        match kind {
+            EnemyKind::Grunt => &self.grunt,
+            EnemyKind::Boss => &self.boss,
+            EnemyKind::Flyer => &self.flyer,
+            EnemyKind::Burrower => &self.burrower,
+            EnemyKind::Leaper => &self.leaper,
+        }
+    }
+}
+
+// Per-enemy AI memory, rebuilt from the archetype every time an enemy spawns
+#[derive(Component)]
+pub struct Brain {
+    movement: Movement,
+    behaviors: Vec<Behavior>,
+    time_in_state: f32,
+    age: f32,
+    // offsets the zig-zag so a crowd doesn't weave in lockstep
+    phase: f32,
+    has_waited: bool,
+    dodge_time: f32,
+    dodge_direction: f32,
+    dodge_cooldown: f32,
+    leap_from: Vec2,
+    // outside pushes like the wall's shockwave, fading out over time
+    knockback: Vec2,
+    // speed multiplier from whatever it's wading through this frame
+    slowed: f32,
+    // the barricade it's stopped at, attacked instead of the wall
+    blocked_by: Option<Entity>,
+}
+
+impl Brain {
+    const FLEE_SPEED: f32 = 1.2;
+    // how far above the flee threshold health must climb before it turns back
+    const RECOVER_MARGIN: f32 = 0.15;
+    const DODGE_TIME: f32 = 0.4;
+    const DODGE_COOLDOWN: f32 = 1.5;
+    // burrowers stay underground between these two x positions
+    const BURROW_FROM: f32 = -450.;
+    const BURROW_TO: f32 = Wall::LEFT - 250.;
+    // leapers only jump a wall that's below this fraction of its health
+    const LEAP_BELOW: f32 = 0.5;
+    const LEAP_TIME: f32 = 0.8;
+    const LEAP_HEIGHT: f32 = 120.;
+    const LEAP_LANDING: f32 = Wall::LEFT + Wall::SIZE;
+    const KNOCKBACK_DRAG: f32 = 6.;
+
+    fn new(archetype: &Archetype, phase: f32) -> Self {
+        Self {
+            movement: archetype.movement,
+            behaviors: archetype.behaviors.clone(),
+            time_in_state: 0.,
+            age: 0.,
+            phase,
+            has_waited: false,
+            dodge_time: 0.,
+            dodge_direction: 0.,
+            dodge_cooldown: 0.,
+            leap_from: Vec2::ZERO,
+            knockback: Vec2::ZERO,
+            slowed: 1.,
+            blocked_by: None,
+        }
+    }
+
+    pub fn knock_back(&mut self, velocity: Vec2) {
+        self.knockback += velocity;
+    }
+
+    // the strongest slow this frame wins
+    pub fn slow(&mut self, factor: f32) {
+        self.slowed = self.slowed.min(factor);
+    }
+
+    pub fn block(&mut self, barricade: Option<Entity>) {
+        self.blocked_by = barricade;
+    }
+
+    pub fn blocked_by(&self) -> Option<Entity> {
+        self.blocked_by
+    }
+
+    fn flee_below(&self) -> Option<f32> {
+        self.behaviors.iter().find_map(|behavior| match behavior {
+            Behavior::Flee { below } => Some(*below),
+            _ => None,
+        })
+    }
+
+    fn group(&self) -> Option<(usize, f32, f32, f32)> {
+        self.behaviors.iter().find_map(|behavior| match behavior {
+            Behavior::WaitForGroup { size, radius, hold_x, patience } => Some((*size, *radius, *hold_x, *patience)),
+            _ => None,
+        })
+    }
+}
+
+// What a transition guard gets to look at
+struct Context<'a> {
+    enemy: &'a Enemy,
+    brain: &'a Brain,
+    position: Vec2,
+    // living enemies within the group radius, not counting this one
+    nearby: usize,
+    wall_health: f32,
+}
+
+impl Context<'_> {
+    fn health_fraction(&self) -> f32 {
+        self.enemy.health() / self.enemy.max_health()
+    }
+}
+
+struct Transition {
+    from: &'static [EnemyState],
+    to: EnemyState,
+    guard: fn(&Context) -> bool,
+}
+
+// Checked top to bottom, the first passing guard wins
+const TRANSITIONS: &[Transition] = &[
+    Transition {
+        from: &[EnemyState::Walk, EnemyState::Wait, EnemyState::Attack, EnemyState::Flee, EnemyState::Leap],
+        to: EnemyState::Death,
+        guard: is_dead,
+    },
+    Transition {
+        from: &[EnemyState::Walk, EnemyState::Wait, EnemyState::Attack],
+        to: EnemyState::Flee,
+        guard: is_panicking,
+    },
+    Transition {
+        from: &[EnemyState::Flee],
+        to: EnemyState::Walk,
+        guard: has_recovered,
+    },
+    Transition {
+        from: &[EnemyState::Walk, EnemyState::Wait, EnemyState::Attack],
+        to: EnemyState::Leap,
+        guard: can_leap,
+    },
+    Transition {
+        from: &[EnemyState::Leap],
+        to: EnemyState::Walk,
+        guard: has_landed,
+    },
+    Transition {
+        from: &[EnemyState::Walk, EnemyState::Wait],
+        to: EnemyState::Attack,
+        guard: at_target,
+    },
+    Transition {
+        from: &[EnemyState::Attack],
+        to: EnemyState::Walk,
+        guard: pushed_off_wall,
+    },
+    Transition {
+        from: &[EnemyState::Walk],
+        to: EnemyState::Wait,
+        guard: should_wait,
+    },
+    Transition {
+        from: &[EnemyState::Wait],
+        to: EnemyState::Walk,
+        guard: has_regrouped,
+    },
+];
+
+fn next_state(state: EnemyState, context: &Context) -> Option<EnemyState> {
+    TRANSITIONS
+        .iter()
+        .find(|transition| transition.from.contains(&state) && (transition.guard)(context))
+        .map(|transition| transition.to)
+}
+
+fn is_dead(context: &Context) -> bool {
+    context.enemy.health() <= 0.
+}
+
+fn is_panicking(context: &Context) -> bool {
+    context.brain.flee_below().is_some_and(|below| context.health_fraction() < below)
+}
+
+fn has_recovered(context: &Context) -> bool {
+    match context.brain.flee_below() {
+        Some(below) => context.health_fraction() >= below + Brain::RECOVER_MARGIN,
+        None => true,
+    }
+}
+
+fn at_wall(context: &Context) -> bool {
+    // flyers go straight over, and anything already past it has nothing to attack
+    context.brain.movement != Movement::Flying
+        && context.position.x >= Wall::LEFT - Wall::SIZE
+        && context.position.x < Wall::LEFT
+}
+
+// the wall, or a barricade standing in the way
+fn at_target(context: &Context) -> bool {
+    at_wall(context) || context.brain.blocked_by.is_some()
+}
+
+fn pushed_off_wall(context: &Context) -> bool {
+    !at_target(context)
+}
+
+fn can_leap(context: &Context) -> bool {
+    context.brain.movement == Movement::Leaping && at_wall(context) && context.wall_health < Brain::LEAP_BELOW
+}
+
+fn has_landed(context: &Context) -> bool {
+    context.brain.time_in_state >= Brain::LEAP_TIME
+}
+
+fn should_wait(context: &Context) -> bool {
+    if context.brain.has_waited {
+        return false;
+    }
+
+    match context.brain.group() {
+        Some((size, _, hold_x, _)) => context.position.x >= hold_x && context.nearby + 1 < size,
+        None => false,
+    }
+}
+
+fn has_regrouped(context: &Context) -> bool {
+    match context.brain.group() {
+        Some((size, _, _, patience)) => context.nearby + 1 >= size || context.brain.time_in_state >= patience,
+        None => true,
+    }
+}
+
+fn on_enter(
+    state: EnemyState,
+    enemy: &Enemy,
+    position: Vec2,
+    brain: &mut Brain,
+    sprite: &mut TextureAtlasSprite,
+    sounds: &mut EventWriter<SoundEvent>,
+    killed: &mut EventWriter<EnemyKilled>,
+) {
+    match state {
+        EnemyState::Attack => sounds.send(SoundEvent(Sound::EnemyAttack)),
+        EnemyState::Death => {
+            sounds.send(SoundEvent(Sound::EnemyDeath));
+            killed.send(EnemyKilled {
+                kind: enemy.kind(),
+                position,
+            });
+        }
+        // turn tail
+        EnemyState::Flee => sprite.flip_x = true,
+        EnemyState::Leap => brain.leap_from = position,
+        EnemyState::Walk | EnemyState::Wait => {}
+    }
+}
+
+fn on_exit(state: EnemyState, brain: &mut Brain, sprite: &mut TextureAtlasSprite, transform: &mut Transform) {
+    match state {
+        EnemyState::Flee => sprite.flip_x = false,
+        // touch down exactly, whatever the last frame of the arc was
+        EnemyState::Leap => {
+            transform.translation.x = Brain::LEAP_LANDING;
+            transform.translation.y = brain.leap_from.y;
+        }
+        // only hold the line once per life
+        EnemyState::Wait => brain.has_waited = true,
+        _ => {}
+    }
+}
+
+fn attach_brains(
+    mut commands: Commands,
+    query: Query<(Entity, &Enemy), Added<Enemy>>,
+    archetypes: Res<Archetypes>,
+    mut game_rng: ResMut<GameRng>,
+) {
+    for (entity, enemy) in query.iter() {
+        let phase = game_rng.rng().gen_range(0. ..TAU);
+
+        commands
+            .entity(entity)
+            .insert(Brain::new(archetypes.get(enemy.kind()), phase))
+            .insert(Elevation::Ground);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn think(
+    mut query: Query<(&Enemy, &mut EnemyState, &mut Brain, &mut Transform, &mut TextureAtlasSprite)>,
+    wall: Res<Wall>,
+    mut sounds: EventWriter<SoundEvent>,
+    mut killed: EventWriter<EnemyKilled>,
+    time: Res<Time>,
+) {
+    let wall_health = wall.health() / wall.max_health();
+
+    let positions: Vec<Vec2> = query
+        .iter()
+        .filter(|(_, state, ..)| **state != EnemyState::Death)
+        .map(|(.., transform, _)| transform.translation.truncate())
+        .collect();
+
+    for (enemy, mut state, mut brain, mut transform, mut sprite) in query.iter_mut() {
+        brain.time_in_state += time.delta_seconds();
+
+        let position = transform.translation.truncate();
+        let nearby = match brain.group() {
+            // the enemy's own position is in the list at distance zero
+            Some((_, radius, _, _)) => positions.iter().filter(|other| other.distance(position) <= radius).count().saturating_sub(1),
+            None => 0,
+        };
+
+        let context = Context {
+            enemy,
+            brain: &brain,
+            position,
+            nearby,
+            wall_health,
+        };
+
+        if let Some(next) = next_state(*state, &context) {
+            if next != *state {
+                on_exit(*state, &mut brain, &mut sprite, &mut transform);
+                on_enter(next, enemy, position, &mut brain, &mut sprite, &mut sounds, &mut killed);
+                brain.time_in_state = 0.;
+                *state = next;
+            }
+        }
+    }
+}
+
+// Add up what every behavior wants and move the enemy
+#[allow(clippy::type_complexity)]
+fn steer(
+    mut commands: Commands,
+    mut pool: ResMut<EntityPool<Enemy>>,
+    mut query: Query<(Entity, &Enemy, &EnemyState, &mut Brain, Option<&Chilled>, Option<&Boss>, &mut Transform)>,
+    projectile_query: Query<(&Projectile, &Transform), Without<Enemy>>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_seconds();
+
+    for (entity, enemy, state, mut brain, chilled, boss, mut transform) in query.iter_mut() {
+        brain.age += dt;
+        brain.dodge_time -= dt;
+        brain.dodge_cooldown -= dt;
+
+        let mut speed = enemy.speed();
+        if chilled.is_some() {
+            speed *= Chilled::SLOW;
+        }
+        if let Some(boss) = boss {
+            speed *= boss.speed_multiplier();
+        }
+        speed *= brain.slowed;
+        brain.slowed = 1.;
+
+        // a leap follows a fixed arc over the wall instead of the behaviors
+        if *state == EnemyState::Leap {
+            let t = (brain.time_in_state / Brain::LEAP_TIME).min(1.);
+            transform.translation.x = brain.leap_from.x + (Brain::LEAP_LANDING - brain.leap_from.x) * t;
+            transform.translation.y = brain.leap_from.y + Brain::LEAP_HEIGHT * (PI * t).sin();
+            continue;
+        }
+
+        let position = transform.translation.truncate();
+        let mut velocity = Vec2::ZERO;
+
+        let mobile = matches!(state, EnemyState::Walk | EnemyState::Wait | EnemyState::Flee);
+        let behaviors = brain.behaviors.clone();
+
+        for behavior in behaviors {
+            match behavior {
+                Behavior::Advance => {
+                    if *state == EnemyState::Walk {
+                        velocity.x += speed;
+                    }
+                }
+                Behavior::ZigZag { amplitude, period } => {
+                    if *state == EnemyState::Walk {
+                        let angular = TAU / period;
+                        velocity.y += amplitude * angular * (brain.age * angular + brain.phase).cos();
+                    }
+                }
+                Behavior::Flee { .. } => {
+                    if *state == EnemyState::Flee {
+                        velocity.x -= speed * Brain::FLEE_SPEED;
+                    }
+                }
+                Behavior::WaitForGroup { .. } => {}
+                Behavior::Dodge { radius, speed: dodge_speed } => {
+                    if !mobile {
+                        continue;
+                    }
+
+                    if brain.dodge_cooldown <= 0. {
+                        // a threat is close, still heading this way and on course to pass through the hurtbox
+                        let threat = projectile_query.iter().find_map(|(projectile, t)| {
+                            let p = t.translation.truncate();
+                            let heading = projectile.velocity().normalize_or_zero();
+                            let offset = position - p;
+
+                            let incoming = offset.dot(heading) > 0.
+                                && offset.length() <= radius
+                                && offset.perp_dot(heading).abs() <= enemy.hurtbox().y / 2. + 25.;
+                            incoming.then_some(p)
+                        });
+
+                        if let Some(threat) = threat {
+                            brain.dodge_time = Brain::DODGE_TIME;
+                            brain.dodge_cooldown = Brain::DODGE_COOLDOWN;
+                            brain.dodge_direction = if position.y >= threat.y { 1. } else { -1. };
+                        }
+                    }
+
+                    if brain.dodge_time > 0. {
+                        velocity.y += brain.dodge_direction * dodge_speed;
+                    }
+                }
+            }
+        }
+
+        velocity += brain.knockback;
+        brain.knockback *= (1. - Brain::KNOCKBACK_DRAG * dt).max(0.);
+
+        transform.translation.x += velocity.x * dt;
+        transform.translation.y = (transform.translation.y + velocity.y * dt).clamp(-385., 385.);
+
+        // a fleeing enemy that makes it off the field is gone for good
+        if *state == EnemyState::Flee && transform.translation.x < -850. {
+            release_enemy(&mut commands, &mut pool, entity);
+        }
+    }
+}
+
+fn elevation_for(brain: &Brain, state: EnemyState, x: f32) -> Elevation {
+    match (brain.movement, state) {
+        (Movement::Flying, _) | (Movement::Leaping, EnemyState::Leap) => Elevation::Air,
+        (Movement::Burrowing, EnemyState::Walk) if (Brain::BURROW_FROM..Brain::BURROW_TO).contains(&x) => {
+            Elevation::Underground
+        }
+        _ => Elevation::Ground,
+    }
+}
+
+fn update_elevation(
+    mut query: Query<(&Brain, &EnemyState, &Transform, &mut Elevation, &mut Visibility)>,
+    mut particles: EventWriter<ParticleBurst>,
+) {
+    for (brain, state, transform, mut elevation, mut visibility) in query.iter_mut() {
+        let next = elevation_for(brain, *state, transform.translation.x);
+        if next == *elevation {
+            continue;
+        }
+
+        // kick up dirt going into and coming out of the ground
+        if next == Elevation::Underground || *elevation == Elevation::Underground {
+            visibility.is_visible = next != Elevation::Underground;
+            particles.send(ParticleBurst::new(ParticlePreset::DeathDust, transform.translation.truncate()));
+        }
+
+        *elevation = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commons::built_in_data;
+
+    const AT_WALL: Vec2 = Vec2::new(Wall::LEFT - 10., 0.);
+    const IN_FIELD: Vec2 = Vec2::new(-600., 0.);
+
+    fn brain(kind: EnemyKind) -> Brain {
+        let archetypes: Archetypes = built_in_data(ARCHETYPES_FILE, BUILT_IN_ARCHETYPES);
+        Brain::new(archetypes.get(kind), 0.)
+    }
+
+    // an enemy of `kind` left with `health` as a fraction of its max
+    fn enemy(kind: EnemyKind, health: f32) -> Enemy {
+        let mut enemy = Enemy::new(kind);
+        enemy.apply_damage(enemy.max_health() * (1. - health));
+        enemy
+    }
+
+    fn next(state: EnemyState, enemy: &Enemy, brain: &Brain, position: Vec2, wall_health: f32) -> Option<EnemyState> {
+        let context = Context {
+            enemy,
+            brain,
+            position,
+            nearby: 0,
+            wall_health,
+        };
+
+        next_state(state, &context)
+    }
+
+    #[test]
+    fn death_beats_everything() {
+        let grunt = brain(EnemyKind::Grunt);
+        let leaper = brain(EnemyKind::Leaper);
+        let dead = enemy(EnemyKind::Grunt, 0.);
+
+        assert_eq!(next(EnemyState::Attack, &dead, &grunt, AT_WALL, 1.), Some(EnemyState::Death));
+        assert_eq!(next(EnemyState::Flee, &dead, &grunt, IN_FIELD, 1.), Some(EnemyState::Death));
+        assert_eq!(next(EnemyState::Leap, &enemy(EnemyKind::Leaper, 0.), &leaper, AT_WALL, 0.1), Some(EnemyState::Death));
+    }
+
+    #[test]
+    fn panic_beats_attacking() {
+        let grunt = brain(EnemyKind::Grunt);
+        let hurt = enemy(EnemyKind::Grunt, 0.1);
+
+        assert_eq!(next(EnemyState::Walk, &hurt, &grunt, AT_WALL, 1.), Some(EnemyState::Flee));
+        assert_eq!(next(EnemyState::Attack, &hurt, &grunt, AT_WALL, 1.), Some(EnemyState::Flee));
+    }
+
+    #[test]
+    fn fleeing_waits_for_health_to_recover() {
+        let grunt = brain(EnemyKind::Grunt);
+
+        assert_eq!(next(EnemyState::Flee, &enemy(EnemyKind::Grunt, 0.25), &grunt, IN_FIELD, 1.), None);
+        assert_eq!(next(EnemyState::Flee, &enemy(EnemyKind::Grunt, 0.5), &grunt, IN_FIELD, 1.), Some(EnemyState::Walk));
+    }
+
+    #[test]
+    fn reaching_the_wall_attacks_before_waiting() {
+        let grunt = brain(EnemyKind::Grunt);
+        let healthy = enemy(EnemyKind::Grunt, 1.);
+
+        // a lone grunt past its hold line would wait for a group, but the wall comes first
+        assert_eq!(next(EnemyState::Walk, &healthy, &grunt, AT_WALL, 1.), Some(EnemyState::Attack));
+        assert_eq!(next(EnemyState::Attack, &healthy, &grunt, AT_WALL, 1.), None);
+        assert_eq!(next(EnemyState::Attack, &healthy, &grunt, IN_FIELD, 1.), Some(EnemyState::Walk));
+    }
+
+    #[test]
+    fn flyers_never_attack_the_wall() {
+        let flyer = brain(EnemyKind::Flyer);
+
+        assert_eq!(next(EnemyState::Walk, &enemy(EnemyKind::Flyer, 1.), &flyer, AT_WALL, 1.), None);
+    }
+
+    #[test]
+    fn leapers_jump_a_weak_wall_instead_of_attacking() {
+        let leaper = brain(EnemyKind::Leaper);
+        let healthy = enemy(EnemyKind::Leaper, 1.);
+
+        assert_eq!(next(EnemyState::Walk, &healthy, &leaper, AT_WALL, 0.3), Some(EnemyState::Leap));
+        assert_eq!(next(EnemyState::Attack, &healthy, &leaper, AT_WALL, 0.3), Some(EnemyState::Leap));
+        assert_eq!(next(EnemyState::Walk, &healthy, &leaper, AT_WALL, 0.8), Some(EnemyState::Attack));
+    }
+}
@@ -0,0 +1,217 @@
+use bevy::prelude::*;
+
+use crate::commons::{AppState, GameTextures};
+use crate::damage::DamageKind;
+use crate::enemy::{spawn_enemy_at, Enemy, EnemyState};
+use crate::feedback::ScreenShake;
+use crate::pool::EntityPool;
+use crate::sound::{Sound, SoundEvent};
+
+pub struct BossPlugin;
+
+impl Plugin for BossPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(AppState::Playing)
+                .with_system(boss_phases)
+                .with_system(summon_minions.after(boss_phases))
+                .with_system(mend_allies.after(boss_phases))
+                .with_system(update_shields.after(boss_phases)),
+        );
+    }
+}
+
+// Bosses change behaviour as they lose health, each phase starting at a
+// lower fraction of max health than the one before
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BossPhase {
+    // walks in slowly while calling grunts to its side
+    Summon,
+    // crawls forward behind a ward that fireballs can't get through
+    Shielded,
+    // patches up anything fighting near it
+    Mend,
+    // drops everything and rushes the wall
+    Charge,
+}
+
+impl BossPhase {
+    fn from_health(fraction: f32) -> Self {
+        if fraction > 0.75 {
+            BossPhase::Summon
+        } else if fraction > 0.5 {
+            BossPhase::Shielded
+        } else if fraction > 0.25 {
+            BossPhase::Mend
+        } else {
+            BossPhase::Charge
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Boss {
+    phase: BossPhase,
+    summon_timer: Timer,
+    mend_timer: Timer,
+}
+
+impl Boss {
+    pub const BOUNTY: usize = 500;
+    const MINIONS_PER_SUMMON: usize = 3;
+    const MEND_RADIUS: f32 = 300.;
+    const MEND_AMOUNT: f32 = 20.;
+
+    // each boss wave brings a tougher boss
+    pub fn max_health(boss_number: usize) -> f32 {
+        1000. + 500. * boss_number.saturating_sub(1) as f32
+    }
+
+    pub fn speed_multiplier(&self) -> f32 {
+        match self.phase {
+            BossPhase::Summon | BossPhase::Mend => 1.,
+            BossPhase::Shielded => 0.5,
+            BossPhase::Charge => 4.,
+        }
+    }
+
+    // the ward only stops fire, frost still gets through
+    pub fn is_immune_to(&self, kind: DamageKind) -> bool {
+        self.phase == BossPhase::Shielded && kind == DamageKind::Fire
+    }
+}
+
+impl Default for Boss {
+    fn default() -> Self {
+        Self {
+            phase: BossPhase::Summon,
+            summon_timer: Timer::from_seconds(4., TimerMode::Repeating),
+            mend_timer: Timer::from_seconds(3., TimerMode::Repeating),
+        }
+    }
+}
+
+// Glow drawn over a boss while its ward is up
+#[derive(Component)]
+struct BossShield {
+    boss: Entity,
+}
+
+pub fn spawn_boss(
+    commands: &mut Commands,
+    pool: &mut EntityPool<Enemy>,
+    game_textures: &GameTextures,
+    position: Vec2,
+    enemy: Enemy,
+) -> Entity {
+    let size = enemy.hurtbox();
+    let entity = spawn_enemy_at(commands, pool, game_textures, position, enemy, EnemyState::Walk);
+    commands.entity(entity).insert(Boss::default());
+
+    commands
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgba(0.4, 0.6, 1., 0.3),
+                custom_size: Some(size * 1.2),
+                ..Default::default()
+            },
+            transform: Transform::from_translation(position.extend(105.)),
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(BossShield { boss: entity });
+
+    entity
+}
+
+fn boss_phases(
+    mut query: Query<(&Enemy, &mut Boss)>,
+    mut shake: ResMut<ScreenShake>,
+    mut sounds: EventWriter<SoundEvent>,
+) {
+    for (enemy, mut boss) in query.iter_mut() {
+        let phase = BossPhase::from_health(enemy.health() / enemy.max_health());
+
+        if phase != boss.phase {
+            boss.phase = phase;
+            shake.add_trauma(0.6);
+            sounds.send(SoundEvent(Sound::EnemyAttack));
+        }
+    }
+}
+
+fn summon_minions(
+    mut commands: Commands,
+    mut pool: ResMut<EntityPool<Enemy>>,
+    game_textures: Res<GameTextures>,
+    mut query: Query<(&mut Boss, &EnemyState, &Transform)>,
+    time: Res<Time>,
+) {
+    for (mut boss, state, transform) in query.iter_mut() {
+        if boss.phase != BossPhase::Summon || *state == EnemyState::Death {
+            continue;
+        }
+
+        if boss.summon_timer.tick(time.delta()).just_finished() {
+            let position = transform.translation.truncate();
+
+            for i in 0..Boss::MINIONS_PER_SUMMON {
+                // fan the minions out behind the boss
+                let offset = Vec2::new(-120., (i as f32 - 1.) * 90.);
+                spawn_enemy_at(
+                    &mut commands,
+                    &mut pool,
+                    &game_textures,
+                    position + offset,
+                    Enemy::default(),
+                    EnemyState::Walk,
+                );
+            }
+        }
+    }
+}
+
+fn mend_allies(
+    mut boss_query: Query<(&mut Boss, &EnemyState, &Transform)>,
+    mut ally_query: Query<(&mut Enemy, &EnemyState, &Transform), Without<Boss>>,
+    time: Res<Time>,
+) {
+    for (mut boss, state, transform) in boss_query.iter_mut() {
+        if boss.phase != BossPhase::Mend || *state == EnemyState::Death {
+            continue;
+        }
+
+        if !boss.mend_timer.tick(time.delta()).just_finished() {
+            continue;
+        }
+
+        for (mut ally, ally_state, ally_transform) in ally_query.iter_mut() {
+            if *ally_state == EnemyState::Death {
+                continue;
+            }
+
+            let distance = ally_transform.translation.truncate().distance(transform.translation.truncate());
+            if distance <= Boss::MEND_RADIUS {
+                ally.heal(Boss::MEND_AMOUNT);
+            }
+        }
+    }
+}
+
+// Keep each shield on its boss and clean it up once the boss is gone
+fn update_shields(
+    mut commands: Commands,
+    mut shield_query: Query<(Entity, &BossShield, &mut Transform, &mut Visibility)>,
+    boss_query: Query<(&Boss, &EnemyState, &Transform), Without<BossShield>>,
+) {
+    for (entity, shield, mut transform, mut visibility) in shield_query.iter_mut() {
+        match boss_query.get(shield.boss) {
+            Ok((boss, state, boss_transform)) => {
+                transform.translation.x = boss_transform.translation.x;
+                transform.translation.y = boss_transform.translation.y;
+                visibility.is_visible = boss.phase == BossPhase::Shielded && *state != EnemyState::Death;
+            }
+            Err(_) => commands.entity(entity).despawn_recursive(),
+        }
+    }
+}
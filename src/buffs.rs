@@ -0,0 +1,259 @@
+use bevy::prelude::*;
+
+use crate::commons::AppState;
+use crate::console::Cheats;
+use crate::player::Player;
+
+pub struct BuffPlugin;
+
+impl Plugin for BuffPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlayerStats>()
+            .add_startup_system(spawn_buff_icons)
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(resolve_stats.label(ResolveStats))
+                    .with_system(update_buff_icons.after(ResolveStats)),
+            );
+    }
+}
+
+// Systems reading `PlayerStats` should run after this to see this frame's buffs
+#[derive(SystemLabel)]
+pub struct ResolveStats;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Stat {
+    MoveSpeed,
+    ProjectileDamage,
+    FireRate,
+    ProjectileCount,
+    WallDamageReduction,
+}
+
+#[derive(Clone, Copy)]
+pub enum Modifier {
+    Add(f32),
+    Multiply(f32),
+}
+
+#[derive(Clone)]
+pub struct Buff {
+    // buffs with the same name refresh each other instead of stacking
+    name: &'static str,
+    // short text drawn on the HUD icon
+    label: &'static str,
+    color: Color,
+    modifiers: Vec<(Stat, Modifier)>,
+    duration: f32,
+    remaining: f32,
+}
+
+impl Buff {
+    pub fn new(name: &'static str, label: &'static str, color: Color, duration: f32) -> Self {
+        Self {
+            name,
+            label,
+            color,
+            modifiers: Vec::new(),
+            duration,
+            remaining: duration,
+        }
+    }
+
+    pub fn with(mut self, stat: Stat, modifier: Modifier) -> Self {
+        self.modifiers.push((stat, modifier));
+        self
+    }
+}
+
+// Timed modifiers on the wizard
+#[derive(Component, Default)]
+pub struct Buffs {
+    active: Vec<Buff>,
+}
+
+impl Buffs {
+    pub fn add(&mut self, buff: Buff) {
+        match self.active.iter_mut().find(|active| active.name == buff.name) {
+            Some(active) => active.remaining = active.remaining.max(buff.duration),
+            None => self.active.push(buff),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.active.clear();
+    }
+
+    // All additions go on before any multipliers
+    fn apply(&self, stat: Stat, base: f32) -> f32 {
+        let modifiers = self
+            .active
+            .iter()
+            .flat_map(|buff| buff.modifiers.iter())
+            .filter(|(modified, _)| *modified == stat);
+
+        let mut added = base;
+        let mut multiplier = 1.;
+        for (_, modifier) in modifiers {
+            match modifier {
+                Modifier::Add(amount) => added += amount,
+                Modifier::Multiply(factor) => multiplier *= factor,
+            }
+        }
+
+        added * multiplier
+    }
+}
+
+// The wizard's stats after every buff, resolved once a frame for everything else to read
+#[derive(Resource)]
+pub struct PlayerStats {
+    move_speed: f32,
+    damage_multiplier: f32,
+    fire_rate: f32,
+    projectile_count: usize,
+    wall_damage_reduction: f32,
+}
+
+impl PlayerStats {
+    pub fn move_speed(&self) -> f32 {
+        self.move_speed
+    }
+
+    pub fn damage_multiplier(&self) -> f32 {
+        self.damage_multiplier
+    }
+
+    // casts per second relative to normal, so cooldowns are divided by it
+    pub fn fire_rate(&self) -> f32 {
+        self.fire_rate
+    }
+
+    pub fn projectile_count(&self) -> usize {
+        self.projectile_count
+    }
+
+    // fraction of incoming wall damage that's ignored
+    pub fn wall_damage_reduction(&self) -> f32 {
+        self.wall_damage_reduction
+    }
+}
+
+impl Default for PlayerStats {
+    fn default() -> Self {
+        Self {
+            move_speed: Player::default().speed(),
+            damage_multiplier: 1.,
+            fire_rate: 1.,
+            projectile_count: 1,
+            wall_damage_reduction: 0.,
+        }
+    }
+}
+
+fn resolve_stats(
+    mut query: Query<(&Player, &mut Buffs)>,
+    mut stats: ResMut<PlayerStats>,
+    cheats: Res<Cheats>,
+    time: Res<Time>,
+) {
+    let (player, mut buffs) = query.single_mut();
+
+    for buff in buffs.active.iter_mut() {
+        buff.remaining -= time.delta_seconds();
+    }
+    buffs.active.retain(|buff| buff.remaining > 0.);
+
+    *stats = PlayerStats {
+        move_speed: buffs.apply(Stat::MoveSpeed, player.speed()).max(0.),
+        damage_multiplier: buffs.apply(Stat::ProjectileDamage, 1.).max(0.),
+        fire_rate: buffs.apply(Stat::FireRate, 1.).max(0.1),
+        projectile_count: buffs.apply(Stat::ProjectileCount, 1.).round().max(1.) as usize,
+        wall_damage_reduction: buffs.apply(Stat::WallDamageReduction, 0.).clamp(0., 1.),
+    };
+
+    // nothing gets through to the wall in god mode
+    if cheats.god() {
+        stats.wall_damage_reduction = 1.;
+    }
+}
+
+// Systems for the buff icons in the top right corner
+const ICON_SLOTS: usize = 6;
+
+#[derive(Component)]
+struct BuffIcon(usize);
+
+fn spawn_buff_icons(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font: Handle<Font> = asset_server.load("font.ttf");
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    right: Val::Px(10.),
+                    top: Val::Px(10.),
+                    ..default()
+                },
+                ..default()
+            },
+            background_color: Color::NONE.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            for slot in 0..ICON_SLOTS {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(56.), Val::Px(56.)),
+                            margin: UiRect::all(Val::Px(4.)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        visibility: Visibility { is_visible: false },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 16.,
+                                color: Color::BLACK,
+                            },
+                        ));
+                    })
+                    .insert(BuffIcon(slot));
+            }
+        });
+}
+
+fn update_buff_icons(
+    buff_query: Query<&Buffs>,
+    mut icon_query: Query<(&BuffIcon, &mut Visibility, &mut BackgroundColor, &Children)>,
+    mut text_query: Query<&mut Text>,
+) {
+    let buffs = buff_query.single();
+
+    for (icon, mut visibility, mut background, children) in icon_query.iter_mut() {
+        let buff = match buffs.active.get(icon.0) {
+            Some(buff) => buff,
+            None => {
+                visibility.is_visible = false;
+                continue;
+            }
+        };
+
+        visibility.is_visible = true;
+        *background = buff.color.into();
+
+        for child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                text.sections[0].value = format!("{}\n{:.0}s", buff.label, buff.remaining.ceil());
+            }
+        }
+    }
+}
@@ -1,14 +1,29 @@
 use bevy::prelude::*;
 use bevy::sprite::collide_aabb::collide;
 
-use crate::{commons::{GameTextures, }, enemy::Enemy, player::Projectile};
+use std::{collections::HashSet, time::Duration};
+
+use crate::commons::{AppState, GameRng};
+use crate::damage::{DamageStyle, Hit, ResolveHits};
+use crate::debug::timed;
+use crate::enemy::{Chilled, Elevation, Enemy, EnemyState};
+use crate::loot::{LootCollected, Pickup};
+use crate::particles::{ParticleBurst, ParticlePreset};
+use crate::player::{release_projectile, Player, Projectile, Spell, Upgrades};
+use crate::pool::EntityPool;
+use crate::sound::{Sound, SoundEvent};
+use crate::traps::{Trap, TrapContact, TrapTriggered};
 
 pub struct CollisionPlugin;
 
 impl Plugin for CollisionPlugin {
     fn build(&self, app: &mut App) {
-        app
-            .add_system(projectile_enemy_collisions);
+        app.add_system_set(
+            SystemSet::on_update(AppState::Playing)
+                .with_system(timed(projectile_enemy_collisions).before(ResolveHits))
+                .with_system(pickup_player_collisions)
+                .with_system(timed(trap_enemy_collisions).label(TrapContact)),
+        );
     }
 }
 
@@ -23,20 +38,60 @@ fn is_collison(center_a: Vec3, size_a: Vec2, center_b: Vec3, size_b: Vec2,) -> b
     collision.is_some()
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn projectile_enemy_collisions(
     mut commands: Commands,
-    mut enemy_query: Query<(&mut Enemy, &Transform)>,
-    mut projectile_query: Query<(Entity, &Projectile, &Transform)>,
+    mut pool: ResMut<EntityPool<Projectile>>,
+    mut enemy_query: Query<(Entity, &Enemy, &EnemyState, Option<&Elevation>, &Transform, &mut TextureAtlasSprite)>,
+    mut projectile_query: Query<(Entity, &mut Projectile, &Transform)>,
+    upgrades: Res<Upgrades>,
+    mut game_rng: ResMut<GameRng>,
+    mut sounds: EventWriter<SoundEvent>,
+    mut hits: EventWriter<Hit>,
+    mut particles: EventWriter<ParticleBurst>,
 ) {
-    for (mut enemy, enemy_transform) in enemy_query.iter_mut() {
-        for (mut entity, projectile, proj_transform) in projectile_query.iter_mut() {
+    // a projectile is only spent once, even if it overlaps two enemies this frame
+    let mut spent = HashSet::new();
+
+    // where everything still standing is, for bouncing projectiles to pick their next target
+    let living: Vec<(Entity, Vec2, Elevation)> = enemy_query
+        .iter()
+        .filter(|(_, _, state, ..)| **state != EnemyState::Death)
+        .map(|(entity, _, _, elevation, transform, _)| {
+            (entity, transform.translation.truncate(), elevation.copied().unwrap_or_default())
+        })
+        .collect();
+
+    for (enemy_entity, enemy, state, elevation, enemy_transform, mut sprite) in enemy_query.iter_mut() {
+        // the dying don't soak up shots meant for the living
+        if *state == EnemyState::Death {
+            continue;
+        }
+
+        let elevation = elevation.copied().unwrap_or_default();
+
+        for (entity, mut projectile, proj_transform) in projectile_query.iter_mut() {
+            if spent.contains(&entity) || !projectile.hits_on_contact() {
+                continue;
+            }
+
+            // a bouncing projectile has to leave its last target before it can hit it again
+            if projectile.last_hit() == Some(enemy_entity) {
+                continue;
+            }
+
+            // passes straight over or under enemies it can't reach
+            if !projectile.spell().can_hit(elevation) {
+                continue;
+            }
+
             let enemy_center = Vec3::new(
                 enemy_transform.translation.x,
                 enemy_transform.translation.y,
                 1.
             );
 
-            let enemy_size = Enemy::get_hurtbox();
+            let enemy_size = enemy.hurtbox();
 
             let proj_center = Vec3::new(
                 proj_transform.translation.x,
@@ -44,14 +99,91 @@ fn projectile_enemy_collisions(
                 1.
             );
 
-            let proj_size = Projectile::get_hitbox();
+            let proj_size = projectile.hitbox();
 
             if is_collison(enemy_center, enemy_size, proj_center, proj_size) {
-                // despawn bullet damage enemy
-                commands.entity(entity).despawn();
-                // damage enemy
-                enemy.apply_damage(projectile.damage());
+                // despawn bullet damage enemy, unless it has somewhere to bounce to
+                spent.insert(entity);
+                let spell = projectile.spell();
+                let candidates: Vec<(Entity, Vec2)> = living
+                    .iter()
+                    .filter(|(_, _, elevation)| spell.can_hit(*elevation))
+                    .map(|(entity, position, _)| (*entity, *position))
+                    .collect();
+
+                if !projectile.bounce(enemy_entity, enemy_transform.translation.truncate(), &candidates) {
+                    release_projectile(&mut commands, &mut pool, entity);
+                }
+
+                hits.send(Hit {
+                    target: enemy_entity,
+                    position: enemy_transform.translation.truncate(),
+                    damage: projectile.damage().roll_crit(
+                        game_rng.rng(),
+                        upgrades.crit_chance(),
+                        upgrades.crit_multiplier(),
+                    ),
+                    spell: Some(projectile.spell()),
+                    style: DamageStyle::Normal,
+                });
+
+                if projectile.spell() == Spell::FrostBolt {
+                    sprite.color = Chilled::TINT;
+                    commands
+                        .entity(enemy_entity)
+                        .insert(Chilled(Timer::new(Duration::from_secs(2), TimerMode::Once)));
+                }
+
+                sounds.send(SoundEvent(Sound::Impact));
+                particles.send(ParticleBurst::new(
+                    ParticlePreset::ImpactSparks,
+                    proj_transform.translation.truncate(),
+                ));
             }
         }
     } 
-}
\ No newline at end of file
+}
+
+fn pickup_player_collisions(
+    mut commands: Commands,
+    pickup_query: Query<(Entity, &Pickup, &Transform)>,
+    player_query: Query<(&Player, &Transform)>,
+    mut collected: EventWriter<LootCollected>,
+) {
+    let (player, player_transform) = player_query.single();
+
+    for (entity, pickup, transform) in pickup_query.iter() {
+        if is_collison(transform.translation, pickup.size(), player_transform.translation, player.size()) {
+            collected.send(LootCollected(pickup.loot()));
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+// Traps sit on the same layers as projectiles, so whatever a trap can't reach walks on by
+fn trap_enemy_collisions(
+    trap_query: Query<(Entity, &Trap, &Transform)>,
+    enemy_query: Query<(Entity, &Enemy, &EnemyState, Option<&Elevation>, &Transform)>,
+    mut triggered: EventWriter<TrapTriggered>,
+) {
+    for (enemy_entity, enemy, state, elevation, enemy_transform) in enemy_query.iter() {
+        if *state == EnemyState::Death {
+            continue;
+        }
+
+        let elevation = elevation.copied().unwrap_or_default();
+
+        for (trap_entity, trap, trap_transform) in trap_query.iter() {
+            if !trap.kind().can_hit(elevation) {
+                continue;
+            }
+
+            if is_collison(enemy_transform.translation, enemy.hurtbox(), trap_transform.translation, trap.size()) {
+                triggered.send(TrapTriggered {
+                    trap: trap_entity,
+                    enemy: enemy_entity,
+                });
+            }
+        }
+    }
+}
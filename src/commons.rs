@@ -1,8 +1,17 @@
-use bevy::prelude::*;
-use std::time::Duration;
+use bevy::{asset::FileAssetIo, prelude::*};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::de::DeserializeOwned;
+use std::{fs, time::Duration};
 
 use crate::enemy::EnemySpawnTimer;
 
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum AppState {
+    MainMenu,
+    Playing,
+    GameOver,
+}
+
 // Resources
 #[derive(Resource)]
 pub struct GameTextures {
@@ -15,6 +24,81 @@ pub struct GameTextures {
     pub wall: Handle<Image>,
 }
 
+#[derive(Resource)]
+pub struct GameSounds {
+    pub cast: Handle<AudioSource>,
+    pub impact: Handle<AudioSource>,
+    pub enemy_death: Handle<AudioSource>,
+    pub enemy_attack: Handle<AudioSource>,
+    pub wall_hit: Handle<AudioSource>,
+    pub wall_critical: Handle<AudioSource>,
+    pub music: Handle<AudioSource>,
+}
+
+#[derive(Resource)]
+pub struct GameRng {
+    rng: StdRng,
+}
+
+impl GameRng {
+    pub fn from_seed(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+
+    // Draw a new seed from the current stream and restart from it, so a run
+    // resumed from that seed rolls the same numbers as this one would have
+    pub fn reseed(&mut self) -> u64 {
+        let seed = self.rng.gen();
+        *self = Self::from_seed(seed);
+        seed
+    }
+}
+
+// Half the size of the visible play area, centred on the origin
+pub const ARENA_HALF_SIZE: Vec2 = Vec2::new(800., 450.);
+
+// Where the mouse is in world space, or None if it's outside the window
+pub fn cursor_world_position(windows: &Windows, camera: &Camera, camera_transform: &GlobalTransform) -> Option<Vec2> {
+    let window = windows.get_primary()?;
+    let cursor = window.cursor_position()?;
+    let window_size = Vec2::new(window.width(), window.height());
+
+    // screen -> normalised device coordinates -> world
+    let ndc = (cursor / window_size) * 2. - Vec2::ONE;
+    let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix().inverse();
+
+    Some(ndc_to_world.project_point3(ndc.extend(-1.)).truncate())
+}
+
+// Data files are read from the same assets folder the asset server uses, so
+// they're found wherever the game is launched from. Each is also built into the
+// game with `include_str!`, and that copy is used if the file can't be read.
+pub fn load_data<T: DeserializeOwned>(file: &str, built_in: &str) -> T {
+    let path = FileAssetIo::get_base_path().join("assets").join(file);
+
+    match fs::read_to_string(&path) {
+        Ok(text) => ron::from_str(&text).unwrap_or_else(|err| {
+            error!("Failed to parse {}, using the built in copy: {}", path.display(), err);
+            built_in_data(file, built_in)
+        }),
+        Err(err) => {
+            warn!("Failed to read {}, using the built in copy: {}", path.display(), err);
+            built_in_data(file, built_in)
+        }
+    }
+}
+
+// The built in copy is the shipped file itself, so it's only broken if that is
+pub fn built_in_data<T: DeserializeOwned>(file: &str, built_in: &str) -> T {
+    ron::from_str(built_in).unwrap_or_else(|err| panic!("built in {} is invalid: {}", file, err))
+}
+
 // Sprite Paths
 pub const PLAYER_SPRITE: &str = "player.png";
 pub const FIREBALL_SPRITE: &str = "fireball.png";
@@ -24,6 +108,15 @@ pub const ENEMY_DEATH: &str = "enemy/enemy_death.png";
 pub const ENEMY_ATTACK: &str = "enemy/enemy_attack.png";
 pub const WALL: &str = "wall.png";
 
+// Sound Paths
+pub const CAST_SOUND: &str = "audio/cast.wav";
+pub const IMPACT_SOUND: &str = "audio/impact.wav";
+pub const ENEMY_DEATH_SOUND: &str = "audio/enemy_death.wav";
+pub const ENEMY_ATTACK_SOUND: &str = "audio/enemy_attack.wav";
+pub const WALL_HIT_SOUND: &str = "audio/wall_hit.wav";
+pub const WALL_CRITICAL_SOUND: &str = "audio/wall_critical.wav";
+pub const MUSIC: &str = "audio/music.wav";
+
 pub fn setup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
@@ -67,6 +160,20 @@ pub fn setup(
     };
     commands.insert_resource(game_textures);
 
+    // Load in game sounds
+    let game_sounds = GameSounds {
+        cast: asset_server.load(CAST_SOUND),
+        impact: asset_server.load(IMPACT_SOUND),
+        enemy_death: asset_server.load(ENEMY_DEATH_SOUND),
+        enemy_attack: asset_server.load(ENEMY_ATTACK_SOUND),
+        wall_hit: asset_server.load(WALL_HIT_SOUND),
+        wall_critical: asset_server.load(WALL_CRITICAL_SOUND),
+        music: asset_server.load(MUSIC),
+    };
+    commands.insert_resource(game_sounds);
+
+    commands.insert_resource(GameRng::from_seed(rand::random()));
+
     commands.insert_resource(EnemySpawnTimer(Timer::new(
         Duration::from_secs(2),
         TimerMode::Repeating,
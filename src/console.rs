@@ -0,0 +1,580 @@
+use bevy::{ecs::system::SystemState, input::InputSystem, prelude::*};
+use rand::Rng;
+use std::{fmt, str::FromStr};
+
+use crate::boss::{spawn_boss, Boss};
+use crate::commons::{AppState, GameRng, GameTextures};
+use crate::debug::DebugOverlay;
+use crate::economy::{Income, Wallet};
+use crate::enemy::{spawn_enemy_at, Enemy, EnemyKind, EnemyState, Wave};
+use crate::player::Player;
+use crate::pool::EntityPool;
+use crate::traps::lane_y;
+use crate::wall::Wall;
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Console>()
+            .init_resource::<CommandRegistry>()
+            .init_resource::<Cheats>()
+            .add_startup_system(spawn_console)
+            // ahead of everything else, so the game never sees what's typed into the console
+            .add_system_to_stage(CoreStage::PreUpdate, console_input.after(InputSystem))
+            .add_system_to_stage(CoreStage::PreUpdate, run_console_commands.after(console_input))
+            .add_system(override_enemy_speed)
+            .add_system(update_console);
+    }
+}
+
+// Toggles set from the console that the rest of the game checks
+#[derive(Resource, Default)]
+pub struct Cheats {
+    // neither the wizard nor the wall can be hurt
+    god: bool,
+    // every enemy moves at this speed, whatever its kind
+    enemy_speed: Option<f32>,
+}
+
+impl Cheats {
+    pub fn god(&self) -> bool {
+        self.god
+    }
+}
+
+#[derive(Debug)]
+pub enum CommandError {
+    Unknown(String),
+    // returned by commands, the registry swaps in the command's usage line
+    BadArguments,
+    Usage(&'static str),
+    Failed(String),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::Unknown(line) => write!(f, "unknown command '{}', try help", line),
+            CommandError::BadArguments => write!(f, "bad arguments"),
+            CommandError::Usage(usage) => write!(f, "usage: {}", usage),
+            CommandError::Failed(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+pub type CommandResult = Result<String, CommandError>;
+
+pub struct ConsoleCommand {
+    // may be more than one word, like `give gold`
+    name: &'static str,
+    usage: &'static str,
+    // offered by tab completion for the first argument
+    hints: &'static [&'static str],
+    run: fn(&mut World, &[&str]) -> CommandResult,
+}
+
+// Every command the console knows. It only needs a World to run against,
+// so commands work the same without the console UI or a window.
+#[derive(Resource)]
+pub struct CommandRegistry {
+    commands: Vec<ConsoleCommand>,
+}
+
+impl CommandRegistry {
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        usage: &'static str,
+        hints: &'static [&'static str],
+        run: fn(&mut World, &[&str]) -> CommandResult,
+    ) -> &mut Self {
+        self.commands.push(ConsoleCommand { name, usage, hints, run });
+        self
+    }
+
+    // The command a line starts with and the words after it, longest name first
+    // so `wall set` isn't mistaken for some shorter `wall`
+    fn find<'a>(&self, words: &'a [&'a str]) -> Option<(&ConsoleCommand, &'a [&'a str])> {
+        self.commands
+            .iter()
+            .filter_map(|command| {
+                let name: Vec<&str> = command.name.split_whitespace().collect();
+                words.starts_with(&name).then(|| (command, &words[name.len()..]))
+            })
+            .max_by_key(|(command, _)| command.name.len())
+    }
+
+    pub fn execute(&self, world: &mut World, line: &str) -> CommandResult {
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        // the registry is out of the world while a command runs, so it answers help itself
+        if words == ["help"] {
+            return Ok(self.usages().join("\n"));
+        }
+
+        let (command, args) = match self.find(&words) {
+            Some(found) => found,
+            None => return Err(CommandError::Unknown(line.trim().to_string())),
+        };
+
+        (command.run)(world, args).map_err(|err| match err {
+            CommandError::BadArguments => CommandError::Usage(command.usage),
+            err => err,
+        })
+    }
+
+    // Whole lines that `line` could be completed to
+    pub fn complete(&self, line: &str) -> Vec<String> {
+        let line = line.trim_start();
+        let mut candidates = Vec::new();
+
+        if "help".starts_with(line) {
+            candidates.push("help".to_string());
+        }
+
+        for command in self.commands.iter() {
+            if command.name.starts_with(line) {
+                candidates.push(command.name.to_string());
+                continue;
+            }
+
+            // past the name, complete the first argument from the hints
+            let argument = match line.strip_prefix(command.name).and_then(|rest| rest.strip_prefix(' ')) {
+                Some(argument) if !argument.contains(' ') => argument,
+                _ => continue,
+            };
+            for hint in command.hints.iter().filter(|hint| hint.starts_with(argument)) {
+                candidates.push(format!("{} {}", command.name, hint));
+            }
+        }
+
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+
+    fn usages(&self) -> Vec<&'static str> {
+        std::iter::once("help").chain(self.commands.iter().map(|command| command.usage)).collect()
+    }
+}
+
+impl Default for CommandRegistry {
+    fn default() -> Self {
+        let mut registry = Self { commands: Vec::new() };
+        registry
+            .register("spawn", "spawn <kind> <count> [lane]", &KIND_NAMES, spawn)
+            .register("give gold", "give gold <amount>", &[], give_gold)
+            .register("wall set", "wall set <health>", &[], set_wall)
+            .register("god", "god", &[], toggle_god)
+            .register("wave skip", "wave skip", &[], skip_wave)
+            .register("timescale", "timescale <factor>", &[], set_timescale)
+            .register("kill all", "kill all", &[], kill_all)
+            .register("set", "set <key> <value>", &SETTINGS, set)
+            .register("debug", "debug", &[], toggle_debug);
+        registry
+    }
+}
+
+fn parse<T: FromStr>(arg: Option<&&str>) -> Result<T, CommandError> {
+    arg.and_then(|arg| arg.parse().ok()).ok_or(CommandError::BadArguments)
+}
+
+const KIND_NAMES: [&str; 5] = ["grunt", "flyer", "burrower", "leaper", "boss"];
+const SETTINGS: [&str; 2] = ["enemy.speed", "player.speed"];
+
+fn parse_kind(name: &str) -> Option<EnemyKind> {
+    match name {
+        "grunt" => Some(EnemyKind::Grunt),
+        "flyer" => Some(EnemyKind::Flyer),
+        "burrower" => Some(EnemyKind::Burrower),
+        "leaper" => Some(EnemyKind::Leaper),
+        "boss" => Some(EnemyKind::Boss),
+        _ => None,
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn spawn(world: &mut World, args: &[&str]) -> CommandResult {
+    let kind = args.first().and_then(|name| parse_kind(name)).ok_or(CommandError::BadArguments)?;
+    let count: usize = parse(args.get(1))?;
+    let lane = match args.get(2) {
+        Some(_) => {
+            let lane: i32 = parse(args.get(2))?;
+            Some(lane_y(lane).ok_or_else(|| CommandError::Failed(format!("no lane {}", lane)))?)
+        }
+        None => None,
+    };
+
+    let mut state: SystemState<(Commands, ResMut<EntityPool<Enemy>>, Res<GameTextures>, ResMut<GameRng>)> =
+        SystemState::new(world);
+    let (mut commands, mut pool, game_textures, mut game_rng) = state.get_mut(world);
+
+    for i in 0..count {
+        let y = lane.unwrap_or_else(|| game_rng.rng().gen_range(-385..385) as f32);
+        // queue them up outside the field rather than stacking them on one spot
+        let position = Vec2::new(-800. - i as f32 * 60., y);
+
+        match kind {
+            EnemyKind::Boss => {
+                let boss = Enemy::boss(Boss::max_health(1));
+                spawn_boss(&mut commands, &mut pool, &game_textures, position, boss);
+            }
+            kind => {
+                spawn_enemy_at(&mut commands, &mut pool, &game_textures, position, Enemy::new(kind), EnemyState::Walk);
+            }
+        }
+    }
+
+    state.apply(world);
+    Ok(format!("spawned {} {}", count, kind.name()))
+}
+
+fn give_gold(world: &mut World, args: &[&str]) -> CommandResult {
+    let amount: usize = parse(args.first())?;
+
+    let mut wallet = world.resource_mut::<Wallet>();
+    wallet.earn(Income::Cheat, amount);
+    Ok(format!("${} banked", wallet.balance()))
+}
+
+fn set_wall(world: &mut World, args: &[&str]) -> CommandResult {
+    let health: f32 = parse(args.first())?;
+
+    let mut wall = world.resource_mut::<Wall>();
+    wall.set_health(health);
+    Ok(format!("wall at {:.0}/{}", wall.health(), wall.max_health()))
+}
+
+fn toggle_god(world: &mut World, _: &[&str]) -> CommandResult {
+    let mut cheats = world.resource_mut::<Cheats>();
+    cheats.god = !cheats.god;
+    Ok(format!("god mode {}", if cheats.god { "on" } else { "off" }))
+}
+
+fn skip_wave(world: &mut World, _: &[&str]) -> CommandResult {
+    let mut wave = world.resource_mut::<Wave>();
+    wave.skip();
+    Ok(format!("on to wave {}", wave.index() + 1))
+}
+
+fn set_timescale(world: &mut World, args: &[&str]) -> CommandResult {
+    let factor: f32 = parse(args.first())?;
+    if !factor.is_finite() || factor < 0. {
+        return Err(CommandError::Failed("timescale can't be negative".to_string()));
+    }
+
+    world.resource_mut::<Time>().set_relative_speed(factor);
+    Ok(format!("timescale {}", factor))
+}
+
+// Dead enemies still go through the usual death, so bounties and loot drop as normal
+fn kill_all(world: &mut World, _: &[&str]) -> CommandResult {
+    let mut query = world.query::<(&mut Enemy, &EnemyState)>();
+
+    let mut killed = 0;
+    for (mut enemy, state) in query.iter_mut(world) {
+        if *state != EnemyState::Death {
+            let health = enemy.health();
+            enemy.apply_damage(health);
+            killed += 1;
+        }
+    }
+
+    Ok(format!("killed {}", killed))
+}
+
+fn set(world: &mut World, args: &[&str]) -> CommandResult {
+    let key = args.first().ok_or(CommandError::BadArguments)?;
+    let value: f32 = parse(args.get(1))?;
+
+    match *key {
+        "enemy.speed" => {
+            world.resource_mut::<Cheats>().enemy_speed = Some(value);
+            for mut enemy in world.query::<&mut Enemy>().iter_mut(world) {
+                enemy.set_speed(value);
+            }
+        }
+        "player.speed" => {
+            for mut player in world.query::<&mut Player>().iter_mut(world) {
+                player.set_speed(value);
+            }
+        }
+        _ => return Err(CommandError::Failed(format!("no setting '{}', try {}", key, SETTINGS.join(", ")))),
+    }
+
+    Ok(format!("{} = {}", key, value))
+}
+
+fn toggle_debug(world: &mut World, _: &[&str]) -> CommandResult {
+    let visible = world.resource_mut::<DebugOverlay>().toggle();
+    Ok(format!("debug overlay {}", if visible { "on" } else { "off" }))
+}
+
+// Keep enemies spawned after `set enemy.speed` in line with the ones before
+fn override_enemy_speed(mut query: Query<&mut Enemy, Added<Enemy>>, cheats: Res<Cheats>) {
+    if let Some(speed) = cheats.enemy_speed {
+        for mut enemy in query.iter_mut() {
+            enemy.set_speed(speed);
+        }
+    }
+}
+
+// Systems for the console itself
+#[derive(Resource, Default)]
+pub struct Console {
+    open: bool,
+    input: String,
+    log: Vec<String>,
+    // lines submitted this frame, run once the world is free
+    pending: Vec<String>,
+}
+
+impl Console {
+    const LOG_LINES: usize = 12;
+
+    fn print(&mut self, text: &str) {
+        self.log.extend(text.lines().map(str::to_string));
+
+        let excess = self.log.len().saturating_sub(Self::LOG_LINES);
+        self.log.drain(..excess);
+    }
+}
+
+#[derive(Component)]
+struct ConsoleWindow;
+
+fn spawn_console(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font: Handle<Font> = asset_server.load("font.ttf");
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(0.),
+                    bottom: Val::Px(0.),
+                    ..default()
+                },
+                size: Size::new(Val::Percent(100.), Val::Auto),
+                padding: UiRect::all(Val::Px(8.)),
+                ..default()
+            },
+            background_color: Color::rgba(0., 0., 0., 0.8).into(),
+            visibility: Visibility { is_visible: false },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "",
+                TextStyle {
+                    font,
+                    font_size: 18.,
+                    color: Color::rgb(0.7, 1., 0.7),
+                },
+            ));
+        })
+        .insert(ConsoleWindow);
+}
+
+// Backtick opens the console during a run, and while it's open it eats the keyboard
+fn console_input(
+    mut keyboard: ResMut<Input<KeyCode>>,
+    mut characters: EventReader<ReceivedCharacter>,
+    mut console: ResMut<Console>,
+    registry: Res<CommandRegistry>,
+    state: Res<State<AppState>>,
+) {
+    if *state.current() != AppState::Playing {
+        console.open = false;
+        characters.clear();
+        return;
+    }
+
+    if keyboard.just_pressed(KeyCode::Grave) {
+        console.open = !console.open;
+        characters.clear();
+        keyboard.reset_all();
+        return;
+    }
+
+    if !console.open {
+        characters.clear();
+        return;
+    }
+
+    for character in characters.iter() {
+        if !character.char.is_control() && character.char != '`' {
+            console.input.push(character.char);
+        }
+    }
+
+    if keyboard.just_pressed(KeyCode::Back) {
+        console.input.pop();
+    }
+
+    if keyboard.just_pressed(KeyCode::Tab) {
+        let candidates = registry.complete(&console.input);
+        match candidates.as_slice() {
+            [] => {}
+            [only] => console.input = format!("{} ", only),
+            several => {
+                // fill in as far as every candidate agrees, then list them
+                let common = several.iter().skip(1).fold(several[0].clone(), |common, candidate| {
+                    common
+                        .chars()
+                        .zip(candidate.chars())
+                        .take_while(|(a, b)| a == b)
+                        .map(|(a, _)| a)
+                        .collect()
+                });
+                let listed = several.join("  ");
+                console.print(&listed);
+                if common.len() > console.input.len() {
+                    console.input = common;
+                }
+            }
+        }
+    }
+
+    if keyboard.just_pressed(KeyCode::Return) {
+        let line = std::mem::take(&mut console.input);
+        if !line.trim().is_empty() {
+            console.print(&format!("> {}", line));
+            console.pending.push(line);
+        }
+    }
+
+    keyboard.reset_all();
+}
+
+fn run_console_commands(world: &mut World) {
+    let pending = std::mem::take(&mut world.resource_mut::<Console>().pending);
+
+    for line in pending {
+        let result = world.resource_scope(|world, registry: Mut<CommandRegistry>| registry.execute(world, &line));
+
+        let mut console = world.resource_mut::<Console>();
+        match result {
+            Ok(output) => console.print(&output),
+            Err(err) => console.print(&format!("error: {}", err)),
+        }
+    }
+}
+
+fn update_console(
+    console: Res<Console>,
+    mut window_query: Query<(&mut Visibility, &Children), With<ConsoleWindow>>,
+    mut text_query: Query<&mut Text>,
+) {
+    if !console.is_changed() {
+        return;
+    }
+
+    let (mut visibility, children) = window_query.single_mut();
+    visibility.is_visible = console.open;
+
+    for child in children.iter() {
+        if let Ok(mut text) = text_query.get_mut(*child) {
+            let mut lines = console.log.clone();
+            lines.push(format!("> {}_", console.input));
+            text.sections[0].value = lines.join("\n");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.insert_resource(Wallet::default());
+        world.insert_resource(Wall::default());
+        world.insert_resource(Cheats::default());
+        world.insert_resource(Wave::default());
+        world.insert_resource(Time::default());
+        world.insert_resource(EntityPool::<Enemy>::default());
+        world
+    }
+
+    #[test]
+    fn give_gold_banks_it_as_a_cheat() {
+        let mut world = world();
+        let registry = CommandRegistry::default();
+
+        assert_eq!(registry.execute(&mut world, "give gold 50").unwrap(), "$50 banked");
+        let wallet = world.resource::<Wallet>();
+        assert_eq!(wallet.balance(), 50);
+        assert_eq!(wallet.earned_from(Income::Cheat), 50);
+    }
+
+    #[test]
+    fn wall_set_changes_health() {
+        let mut world = world();
+        let registry = CommandRegistry::default();
+
+        assert_eq!(registry.execute(&mut world, "wall set 10").unwrap(), "wall at 10/200");
+        assert_eq!(world.resource::<Wall>().health(), 10.);
+    }
+
+    #[test]
+    fn god_toggles() {
+        let mut world = world();
+        let registry = CommandRegistry::default();
+
+        assert_eq!(registry.execute(&mut world, "god").unwrap(), "god mode on");
+        assert!(world.resource::<Cheats>().god());
+        assert_eq!(registry.execute(&mut world, "god").unwrap(), "god mode off");
+        assert!(!world.resource::<Cheats>().god());
+    }
+
+    #[test]
+    fn negative_timescale_is_refused() {
+        let mut world = world();
+        let registry = CommandRegistry::default();
+
+        let err = registry.execute(&mut world, "timescale -1").unwrap_err();
+        assert_eq!(err.to_string(), "timescale can't be negative");
+        assert_eq!(world.resource::<Time>().relative_speed(), 1.);
+
+        assert_eq!(registry.execute(&mut world, "timescale 0.5").unwrap(), "timescale 0.5");
+        assert_eq!(world.resource::<Time>().relative_speed(), 0.5);
+    }
+
+    #[test]
+    fn bad_lines_are_reported() {
+        let mut world = world();
+        let registry = CommandRegistry::default();
+
+        let err = registry.execute(&mut world, "fly away").unwrap_err();
+        assert!(matches!(&err, CommandError::Unknown(line) if line == "fly away"));
+
+        let err = registry.execute(&mut world, "give gold lots").unwrap_err();
+        assert!(matches!(err, CommandError::Usage("give gold <amount>")));
+        assert_eq!(world.resource::<Wallet>().balance(), 0);
+    }
+
+    #[test]
+    fn completes_names_and_hints() {
+        let registry = CommandRegistry::default();
+
+        assert_eq!(registry.complete("wa"), ["wall set", "wave skip"]);
+        assert_eq!(registry.complete("spawn g"), ["spawn grunt"]);
+        assert_eq!(
+            registry.complete(""),
+            [
+                "debug",
+                "give gold",
+                "god",
+                "help",
+                "kill all",
+                "set",
+                "spawn",
+                "timescale",
+                "wall set",
+                "wave skip",
+            ]
+        );
+    }
+}
@@ -0,0 +1,275 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::boss::Boss;
+use crate::commons::AppState;
+use crate::debug::timed;
+use crate::enemy::{Chilled, Enemy, EnemyKind};
+use crate::player::Spell;
+use crate::stats::RunStats;
+
+pub struct DamagePlugin;
+
+impl Plugin for DamagePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Hit>()
+            .add_event::<DamageDealt>()
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing).with_system(timed(resolve_hits).label(ResolveHits)),
+            );
+    }
+}
+
+// Anything that sends `Hit` events should run before this so the damage lands the same frame
+#[derive(SystemLabel)]
+pub struct ResolveHits;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DamageKind {
+    Fire,
+    Frost,
+    Lightning,
+    Arcane,
+    Physical,
+}
+
+#[derive(Clone, Copy)]
+pub struct Damage {
+    pub amount: f32,
+    pub kind: DamageKind,
+    pub crit: bool,
+}
+
+impl Damage {
+    pub fn new(amount: f32, kind: DamageKind) -> Self {
+        Self {
+            amount,
+            kind,
+            crit: false,
+        }
+    }
+
+    pub fn roll_crit(self, rng: &mut impl Rng, chance: f32, multiplier: f32) -> Self {
+        if rng.gen::<f32>() < chance {
+            Self {
+                amount: self.amount * multiplier,
+                crit: true,
+                ..self
+            }
+        } else {
+            self
+        }
+    }
+}
+
+// Fraction of each kind of damage an enemy shrugs off, negative for a weakness.
+// Armor is taken off every hit first, except arcane which goes straight through.
+#[derive(Component, Clone, Copy, Default)]
+pub struct Resistances {
+    pub armor: f32,
+    pub fire: f32,
+    pub frost: f32,
+    pub lightning: f32,
+    pub arcane: f32,
+    pub physical: f32,
+}
+
+impl Resistances {
+    pub fn for_kind(kind: EnemyKind) -> Self {
+        match kind {
+            EnemyKind::Grunt => Self {
+                armor: 2.,
+                ..Default::default()
+            },
+            EnemyKind::Boss => Self {
+                armor: 8.,
+                fire: 0.25,
+                frost: 0.25,
+                ..Default::default()
+            },
+            EnemyKind::Flyer => Self {
+                lightning: -0.5,
+                physical: -0.25,
+                ..Default::default()
+            },
+            EnemyKind::Burrower => Self {
+                armor: 5.,
+                frost: -0.25,
+                physical: 0.25,
+                ..Default::default()
+            },
+            EnemyKind::Leaper => Self {
+                armor: 3.,
+                fire: -0.25,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn get(&self, kind: DamageKind) -> f32 {
+        match kind {
+            DamageKind::Fire => self.fire,
+            DamageKind::Frost => self.frost,
+            DamageKind::Lightning => self.lightning,
+            DamageKind::Arcane => self.arcane,
+            DamageKind::Physical => self.physical,
+        }
+    }
+
+    fn mitigate(&self, damage: Damage) -> f32 {
+        let after_armor = match damage.kind {
+            DamageKind::Arcane => damage.amount,
+            _ => damage.amount - self.armor,
+        };
+
+        (after_armor * (1. - self.get(damage.kind))).max(0.)
+    }
+}
+
+// Something struck an enemy and the pipeline should work out how much it hurts
+pub struct Hit {
+    pub target: Entity,
+    pub position: Vec2,
+    pub damage: Damage,
+    // the spell it came from, for run stats
+    pub spell: Option<Spell>,
+    // Status for ticks and effects rather than a direct strike
+    pub style: DamageStyle,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum DamageStyle {
+    Normal,
+    Crit,
+    Status,
+}
+
+// What actually came off an enemy's health after every modifier
+pub struct DamageDealt {
+    pub target: Entity,
+    pub position: Vec2,
+    pub amount: f32,
+    pub kind: DamageKind,
+    pub style: DamageStyle,
+}
+
+impl Chilled {
+    // frozen enemies are brittle
+    fn modifier(kind: DamageKind) -> f32 {
+        match kind {
+            DamageKind::Physical | DamageKind::Lightning => 1.25,
+            _ => 1.,
+        }
+    }
+}
+
+// Shields, then armor and resistances, then status effects
+#[allow(clippy::type_complexity)]
+fn resolve_hits(
+    mut hits: EventReader<Hit>,
+    mut enemy_query: Query<(&mut Enemy, Option<&Resistances>, Option<&Boss>, Option<&Chilled>)>,
+    mut stats: ResMut<RunStats>,
+    mut damage_dealt: EventWriter<DamageDealt>,
+) {
+    for hit in hits.iter() {
+        let (mut enemy, resistances, boss, chilled) = match enemy_query.get_mut(hit.target) {
+            Ok(enemy) => enemy,
+            Err(_) => continue,
+        };
+
+        if boss.is_some_and(|boss| boss.is_immune_to(hit.damage.kind)) {
+            continue;
+        }
+
+        let mut amount = resistances.copied().unwrap_or_default().mitigate(hit.damage);
+        let brittle = chilled.is_some() && Chilled::modifier(hit.damage.kind) > 1.;
+        if chilled.is_some() {
+            amount *= Chilled::modifier(hit.damage.kind);
+        }
+
+        enemy.apply_damage(amount);
+        if let Some(spell) = hit.spell {
+            stats.record_hit(spell, amount);
+        }
+
+        damage_dealt.send(DamageDealt {
+            target: hit.target,
+            position: hit.position,
+            amount,
+            kind: hit.damage.kind,
+            style: match hit.style {
+                DamageStyle::Normal if hit.damage.crit => DamageStyle::Crit,
+                // the chill is what made it hurt more
+                DamageStyle::Normal if brittle => DamageStyle::Status,
+                style => style,
+            },
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(hit: Hit, chilled: bool) -> DamageStyle {
+        let mut world = World::new();
+        world.init_resource::<Events<Hit>>();
+        world.init_resource::<Events<DamageDealt>>();
+        world.init_resource::<RunStats>();
+
+        let mut enemy = world.spawn(Enemy::new(EnemyKind::Grunt));
+        if chilled {
+            enemy.insert(Chilled(Timer::from_seconds(2., TimerMode::Once)));
+        }
+        let target = enemy.id();
+
+        world.resource_mut::<Events<Hit>>().send(Hit { target, ..hit });
+        let mut stage = SystemStage::single_threaded();
+        stage.add_system(resolve_hits);
+        stage.run(&mut world);
+
+        let events = world.resource::<Events<DamageDealt>>();
+        let dealt: Vec<_> = events.get_reader().iter(events).map(|dealt| dealt.style).collect();
+        assert_eq!(dealt.len(), 1);
+        dealt[0]
+    }
+
+    fn hit(damage: Damage, style: DamageStyle) -> Hit {
+        Hit {
+            target: Entity::from_raw(0),
+            position: Vec2::ZERO,
+            damage,
+            spell: None,
+            style,
+        }
+    }
+
+    #[test]
+    fn status_hits_come_out_styled_as_status() {
+        let style = resolve(hit(Damage::new(5., DamageKind::Fire), DamageStyle::Status), false);
+        assert!(style == DamageStyle::Status);
+    }
+
+    #[test]
+    fn direct_hits_are_normal_unless_they_crit() {
+        let style = resolve(hit(Damage::new(5., DamageKind::Fire), DamageStyle::Normal), false);
+        assert!(style == DamageStyle::Normal);
+
+        let crit = Damage {
+            crit: true,
+            ..Damage::new(5., DamageKind::Fire)
+        };
+        let style = resolve(hit(crit, DamageStyle::Normal), false);
+        assert!(style == DamageStyle::Crit);
+    }
+
+    #[test]
+    fn hits_made_worse_by_the_chill_are_status() {
+        let style = resolve(hit(Damage::new(5., DamageKind::Physical), DamageStyle::Normal), true);
+        assert!(style == DamageStyle::Status);
+
+        let style = resolve(hit(Damage::new(5., DamageKind::Fire), DamageStyle::Normal), true);
+        assert!(style == DamageStyle::Normal);
+    }
+}
@@ -0,0 +1,579 @@
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticId, Diagnostics, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin},
+    ecs::{
+        archetype::ArchetypeComponentId,
+        component::ComponentId,
+        query::Access,
+        schedule::SystemLabelId,
+    },
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use std::{
+    borrow::Cow,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use crate::enemy::{Enemy, EnemyState};
+use crate::loot::Pickup;
+use crate::player::{Player, Projectile};
+use crate::traps::Trap;
+use crate::wall::Wall;
+
+pub struct DebugPlugin;
+
+impl Plugin for DebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(FrameTimeDiagnosticsPlugin)
+            .add_plugin(EntityCountDiagnosticsPlugin)
+            .init_resource::<DebugOverlay>()
+            .init_resource::<StageClock>()
+            .init_resource::<SystemTimes>()
+            .init_resource::<DebugOutlines>()
+            .add_startup_system(setup_stage_diagnostics)
+            .add_startup_system(spawn_debug_panel)
+            .add_startup_system(spawn_wall_line)
+            .add_system(toggle_debug_overlay)
+            .add_system(update_debug_panel)
+            .add_system(timed(draw_debug_shapes))
+            .add_system_to_stage(CoreStage::Last, record_system_times);
+
+        // time each stage from a marker stage just before it to one just after
+        for (stage, before, after, id) in TIMED_STAGES {
+            app.add_stage_before(stage.clone(), before, SystemStage::single(start_stage_clock))
+                .add_stage_after(stage, after, SystemStage::single(record_stage_time(id)));
+        }
+    }
+}
+
+// Whether hitboxes, enemy labels and the performance panel are drawn
+#[derive(Resource, Default)]
+pub struct DebugOverlay {
+    visible: bool,
+}
+
+impl DebugOverlay {
+    pub fn toggle(&mut self) -> bool {
+        self.visible = !self.visible;
+        self.visible
+    }
+}
+
+fn toggle_debug_overlay(keyboard: Res<Input<KeyCode>>, mut overlay: ResMut<DebugOverlay>) {
+    if keyboard.just_pressed(KeyCode::F3) {
+        overlay.toggle();
+    }
+}
+
+// Systems for timing the frame stage by stage
+#[derive(StageLabel)]
+enum TimingStage {
+    PreUpdateStart,
+    PreUpdateEnd,
+    UpdateStart,
+    UpdateEnd,
+    PostUpdateStart,
+    PostUpdateEnd,
+}
+
+const PRE_UPDATE_TIME: DiagnosticId = DiagnosticId::from_u128(0x5c1f_6a2e_0b4d_4f0e_9a51_7e3c_2d18_0001);
+const UPDATE_TIME: DiagnosticId = DiagnosticId::from_u128(0x5c1f_6a2e_0b4d_4f0e_9a51_7e3c_2d18_0002);
+const POST_UPDATE_TIME: DiagnosticId = DiagnosticId::from_u128(0x5c1f_6a2e_0b4d_4f0e_9a51_7e3c_2d18_0003);
+
+// (stage, marker before, marker after, diagnostic)
+const TIMED_STAGES: [(CoreStage, TimingStage, TimingStage, DiagnosticId); 3] = [
+    (CoreStage::PreUpdate, TimingStage::PreUpdateStart, TimingStage::PreUpdateEnd, PRE_UPDATE_TIME),
+    // all the gameplay systems live here
+    (CoreStage::Update, TimingStage::UpdateStart, TimingStage::UpdateEnd, UPDATE_TIME),
+    // transforms and ui layout
+    (CoreStage::PostUpdate, TimingStage::PostUpdateStart, TimingStage::PostUpdateEnd, POST_UPDATE_TIME),
+];
+
+#[derive(Resource)]
+struct StageClock(Instant);
+
+impl Default for StageClock {
+    fn default() -> Self {
+        Self(Instant::now())
+    }
+}
+
+fn setup_stage_diagnostics(mut diagnostics: ResMut<Diagnostics>) {
+    diagnostics.add(Diagnostic::new(PRE_UPDATE_TIME, "pre_update_ms", 20));
+    diagnostics.add(Diagnostic::new(UPDATE_TIME, "update_ms", 20));
+    diagnostics.add(Diagnostic::new(POST_UPDATE_TIME, "post_update_ms", 20));
+}
+
+fn start_stage_clock(mut clock: ResMut<StageClock>) {
+    clock.0 = Instant::now();
+}
+
+fn record_stage_time(id: DiagnosticId) -> impl FnMut(Res<StageClock>, ResMut<Diagnostics>) {
+    move |clock: Res<StageClock>, mut diagnostics: ResMut<Diagnostics>| {
+        diagnostics.add_measurement(id, || clock.0.elapsed().as_secs_f64() * 1000.);
+    }
+}
+
+// Wraps a system so every run of it is timed and reported as its own diagnostic
+pub struct Timed<S> {
+    system: S,
+    pending: Option<PendingTimes>,
+}
+
+pub fn timed<S, Params>(system: S) -> Timed<S::System>
+where
+    S: IntoSystem<(), (), Params>,
+{
+    Timed {
+        system: IntoSystem::into_system(system),
+        pending: None,
+    }
+}
+
+impl<S: System<In = (), Out = ()>> Timed<S> {
+    fn record(&self, start: Instant) {
+        if let Some(pending) = &self.pending {
+            let elapsed = start.elapsed().as_secs_f64() * 1000.;
+            *pending.lock().unwrap().entry(self.system.name()).or_default() += elapsed;
+        }
+    }
+}
+
+impl<S: System<In = (), Out = ()>> System for Timed<S> {
+    type In = ();
+    type Out = ();
+
+    fn name(&self) -> Cow<'static, str> {
+        self.system.name()
+    }
+
+    fn component_access(&self) -> &Access<ComponentId> {
+        self.system.component_access()
+    }
+
+    fn archetype_component_access(&self) -> &Access<ArchetypeComponentId> {
+        self.system.archetype_component_access()
+    }
+
+    fn is_send(&self) -> bool {
+        self.system.is_send()
+    }
+
+    fn is_exclusive(&self) -> bool {
+        self.system.is_exclusive()
+    }
+
+    unsafe fn run_unsafe(&mut self, input: (), world: &World) {
+        let start = Instant::now();
+        self.system.run_unsafe(input, world);
+        self.record(start);
+    }
+
+    fn run(&mut self, input: (), world: &mut World) {
+        let start = Instant::now();
+        self.system.run(input, world);
+        self.record(start);
+    }
+
+    fn apply_buffers(&mut self, world: &mut World) {
+        self.system.apply_buffers(world);
+    }
+
+    fn initialize(&mut self, world: &mut World) {
+        self.pending = Some(world.get_resource_or_insert_with(SystemTimes::default).pending.clone());
+        self.system.initialize(world);
+    }
+
+    fn update_archetype_component_access(&mut self, world: &World) {
+        self.system.update_archetype_component_access(world);
+    }
+
+    fn check_change_tick(&mut self, change_tick: u32) {
+        self.system.check_change_tick(change_tick);
+    }
+
+    // keeps ordering against the bare function working
+    fn default_labels(&self) -> Vec<SystemLabelId> {
+        self.system.default_labels()
+    }
+
+    fn get_last_change_tick(&self) -> u32 {
+        self.system.get_last_change_tick()
+    }
+
+    fn set_last_change_tick(&mut self, last_change_tick: u32) {
+        self.system.set_last_change_tick(last_change_tick);
+    }
+}
+
+// milliseconds spent in each timed system since the last report, shared with
+// the wrappers which may run on any thread
+type PendingTimes = Arc<Mutex<HashMap<Cow<'static, str>, f64>>>;
+
+const FIRST_SYSTEM_TIME: u128 = 0x5c1f_6a2e_0b4d_4f0e_9a51_7e3c_2d18_1000;
+
+#[derive(Resource, Default)]
+struct SystemTimes {
+    pending: PendingTimes,
+    ids: HashMap<Cow<'static, str>, DiagnosticId>,
+}
+
+fn record_system_times(mut times: ResMut<SystemTimes>, mut diagnostics: ResMut<Diagnostics>) {
+    let times = &mut *times;
+    for (name, elapsed) in times.pending.lock().unwrap().drain() {
+        let next = FIRST_SYSTEM_TIME + times.ids.len() as u128;
+        let id = *times.ids.entry(name.clone()).or_insert_with(|| {
+            let id = DiagnosticId::from_u128(next);
+            let short = name.trim_start_matches(concat!(env!("CARGO_CRATE_NAME"), "::")).to_string();
+            diagnostics.add(Diagnostic::new(id, short, 20));
+            id
+        });
+        diagnostics.add_measurement(id, || elapsed);
+    }
+}
+
+// Systems for the performance panel
+#[derive(Component)]
+struct DebugPanel;
+
+fn spawn_debug_panel(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font: Handle<Font> = asset_server.load("font.ttf");
+
+    commands
+        .spawn(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font,
+                    font_size: 18.,
+                    color: Color::YELLOW,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    right: Val::Px(10.),
+                    top: Val::Px(80.),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert(Visibility { is_visible: false })
+        .insert(DebugPanel);
+}
+
+const SLOWEST_SYSTEMS: usize = 6;
+
+fn update_debug_panel(
+    overlay: Res<DebugOverlay>,
+    diagnostics: Res<Diagnostics>,
+    times: Res<SystemTimes>,
+    shape_query: Query<(), With<DebugShape>>,
+    mut query: Query<(&mut Text, &mut Visibility), With<DebugPanel>>,
+) {
+    let (mut text, mut visibility) = query.single_mut();
+    visibility.is_visible = overlay.visible;
+    if !overlay.visible {
+        return;
+    }
+
+    let smoothed = |id| diagnostics.get(id).and_then(|diagnostic| diagnostic.smoothed()).unwrap_or(0.);
+    let average = |id| diagnostics.get(id).and_then(|diagnostic| diagnostic.average()).unwrap_or(0.);
+
+    let mut systems: Vec<_> = times
+        .ids
+        .values()
+        .filter_map(|&id| diagnostics.get(id))
+        .map(|diagnostic| (diagnostic.name.as_ref(), diagnostic.average().unwrap_or(0.)))
+        .collect();
+    systems.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let mut value = format!(
+        "FPS {:.0} ({:.1} ms)\nEntities {:.0}\nPreUpdate {:.2} ms\nUpdate {:.2} ms\nPostUpdate {:.2} ms",
+        smoothed(FrameTimeDiagnosticsPlugin::FPS),
+        smoothed(FrameTimeDiagnosticsPlugin::FRAME_TIME) * 1000.,
+        // the overlay's own shapes would otherwise swamp the count
+        diagnostics
+            .get(EntityCountDiagnosticsPlugin::ENTITY_COUNT)
+            .and_then(|diagnostic| diagnostic.value())
+            .map_or(0., |count| (count - shape_query.iter().count() as f64).max(0.)),
+        average(PRE_UPDATE_TIME),
+        average(UPDATE_TIME),
+        average(POST_UPDATE_TIME),
+    );
+    for (name, average) in systems.into_iter().take(SLOWEST_SYSTEMS) {
+        value.push_str(&format!("\n{} {:.2} ms", name, average));
+    }
+    text.sections[0].value = value;
+}
+
+// Systems for the shapes drawn over the field. Each target gets one outline
+// that follows it until the target goes away or the overlay is hidden.
+#[derive(Component)]
+struct DebugShape;
+
+#[derive(Component)]
+struct DebugOutline {
+    size: Vec2,
+    label: Option<Entity>,
+}
+
+// the line enemies stop at to attack the wall
+#[derive(Component)]
+struct WallLine;
+
+// outline entity for each entity being outlined
+#[derive(Resource, Default)]
+struct DebugOutlines(HashMap<Entity, Entity>);
+
+const OUTLINE: f32 = 2.;
+// above every sprite in the game
+const DEBUG_Z: f32 = 500.;
+
+fn spawn_wall_line(mut commands: Commands) {
+    commands
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                color: Color::FUCHSIA,
+                custom_size: Some(Vec2::new(OUTLINE, 900.)),
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(Wall::LEFT - Wall::SIZE, 0., DEBUG_Z),
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(DebugShape)
+        .insert(WallLine);
+}
+
+fn spawn_outline(
+    commands: &mut Commands,
+    font: &Handle<Font>,
+    center: Vec2,
+    size: Vec2,
+    color: Color,
+    label: Option<String>,
+) -> Entity {
+    let half = size / 2.;
+    let edges = [
+        (Vec2::new(0., half.y), Vec2::new(size.x, OUTLINE)),
+        (Vec2::new(0., -half.y), Vec2::new(size.x, OUTLINE)),
+        (Vec2::new(-half.x, 0.), Vec2::new(OUTLINE, size.y)),
+        (Vec2::new(half.x, 0.), Vec2::new(OUTLINE, size.y)),
+    ];
+
+    let mut label_entity = None;
+    commands
+        .spawn(SpatialBundle::from_transform(Transform::from_translation(center.extend(DEBUG_Z))))
+        .insert(DebugShape)
+        .with_children(|parent| {
+            for (offset, edge) in edges {
+                parent
+                    .spawn(SpriteBundle {
+                        sprite: Sprite {
+                            color,
+                            custom_size: Some(edge),
+                            ..Default::default()
+                        },
+                        transform: Transform::from_translation(offset.extend(0.)),
+                        ..Default::default()
+                    })
+                    .insert(DebugShape);
+            }
+
+            if let Some(label) = label {
+                let entity = parent
+                    .spawn(Text2dBundle {
+                        text: Text::from_section(
+                            label,
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 14.,
+                                color: Color::WHITE,
+                            },
+                        )
+                        .with_alignment(TextAlignment::CENTER),
+                        transform: Transform::from_xyz(0., half.y + 12., 0.),
+                        ..Default::default()
+                    })
+                    .insert(DebugShape)
+                    .id();
+                label_entity = Some(entity);
+            }
+        })
+        .insert(DebugOutline {
+            size,
+            label: label_entity,
+        })
+        .id()
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn draw_debug_shapes(
+    mut commands: Commands,
+    overlay: Res<DebugOverlay>,
+    mut outlines: ResMut<DebugOutlines>,
+    mut outline_query: Query<(&DebugOutline, &mut Transform), With<DebugShape>>,
+    mut label_query: Query<&mut Text, With<DebugShape>>,
+    mut wall_line_query: Query<&mut Visibility, With<WallLine>>,
+    enemy_query: Query<(Entity, &Enemy, &EnemyState, &Transform), Without<DebugShape>>,
+    projectile_query: Query<(Entity, &Projectile, &Transform), Without<DebugShape>>,
+    player_query: Query<(Entity, &Player, &Transform), Without<DebugShape>>,
+    pickup_query: Query<(Entity, &Pickup, &Transform), Without<DebugShape>>,
+    trap_query: Query<(Entity, &Trap, &Transform), Without<DebugShape>>,
+    asset_server: Res<AssetServer>,
+) {
+    if overlay.is_changed() {
+        wall_line_query.single_mut().is_visible = overlay.visible;
+    }
+
+    if !overlay.visible {
+        for (_, outline) in outlines.0.drain() {
+            commands.entity(outline).despawn_recursive();
+        }
+        return;
+    }
+
+    // (target, position, size, color, label)
+    let mut targets = Vec::new();
+    for (entity, enemy, state, transform) in enemy_query.iter() {
+        let label = format!("{:?} {:.0}/{:.0}", state, enemy.health(), enemy.max_health());
+        targets.push((entity, transform, enemy.hurtbox(), Color::RED, Some(label)));
+    }
+    for (entity, projectile, transform) in projectile_query.iter() {
+        targets.push((entity, transform, projectile.hitbox(), Color::YELLOW, None));
+    }
+    for (entity, player, transform) in player_query.iter() {
+        targets.push((entity, transform, player.size(), Color::GREEN, None));
+    }
+    for (entity, pickup, transform) in pickup_query.iter() {
+        targets.push((entity, transform, pickup.size(), Color::CYAN, None));
+    }
+    for (entity, trap, transform) in trap_query.iter() {
+        targets.push((entity, transform, trap.size(), Color::ORANGE, None));
+    }
+
+    let font: Handle<Font> = asset_server.load("font.ttf");
+    let mut seen = HashSet::new();
+    for (target, transform, size, color, label) in targets {
+        seen.insert(target);
+        let position = transform.translation.truncate();
+        let existing = outlines.0.get(&target).copied();
+
+        match existing.and_then(|outline| outline_query.get_mut(outline).ok()) {
+            Some((outline, mut outline_transform)) if outline.size == size => {
+                outline_transform.translation = position.extend(DEBUG_Z);
+                if let (Some(entity), Some(label)) = (outline.label, label) {
+                    if let Ok(mut text) = label_query.get_mut(entity) {
+                        if text.sections[0].value != label {
+                            text.sections[0].value = label;
+                        }
+                    }
+                }
+            }
+            // a new target, or one whose size changed since it was outlined
+            _ => {
+                if let Some(outline) = existing {
+                    commands.entity(outline).despawn_recursive();
+                }
+                let outline = spawn_outline(&mut commands, &font, position, size, color, label);
+                outlines.0.insert(target, outline);
+            }
+        }
+    }
+
+    outlines.0.retain(|target, outline| {
+        let alive = seen.contains(target);
+        if !alive {
+            commands.entity(*outline).despawn_recursive();
+        }
+        alive
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enemy::EnemyKind;
+    use bevy::{
+        asset::FileAssetIo,
+        tasks::{IoTaskPool, TaskPool},
+    };
+
+    fn idle() {}
+
+    #[test]
+    fn timed_systems_report_their_own_diagnostic() {
+        let mut world = World::new();
+        world.init_resource::<Diagnostics>();
+        world.init_resource::<SystemTimes>();
+
+        let mut stage = SystemStage::single_threaded();
+        stage.add_system(timed(idle)).add_system(record_system_times.after(idle));
+        stage.run(&mut world);
+        stage.run(&mut world);
+
+        let times = world.resource::<SystemTimes>();
+        assert_eq!(times.ids.len(), 1);
+        let id = times.ids.values().next().copied().unwrap();
+        let diagnostic = world.resource::<Diagnostics>().get(id).unwrap();
+        assert_eq!(diagnostic.name, "debug::tests::idle");
+        assert_eq!(diagnostic.history_len(), 2);
+    }
+
+    fn outline_world() -> (World, SystemStage) {
+        IoTaskPool::init(TaskPool::default);
+        let mut world = World::new();
+        world.insert_resource(AssetServer::new(FileAssetIo::new("assets", false)));
+        world.insert_resource(DebugOverlay { visible: true });
+        world.init_resource::<DebugOutlines>();
+        world.spawn((WallLine, DebugShape, Visibility { is_visible: false }));
+
+        let mut stage = SystemStage::single_threaded();
+        stage.add_system(draw_debug_shapes);
+        (world, stage)
+    }
+
+    #[test]
+    fn outlines_follow_their_target_instead_of_being_respawned() {
+        let (mut world, mut stage) = outline_world();
+        let enemy = world
+            .spawn((Enemy::new(EnemyKind::Grunt), EnemyState::Walk, Transform::default()))
+            .id();
+        world.spawn((Player::default(), Transform::default()));
+
+        stage.run(&mut world);
+        let shapes = world.query_filtered::<(), With<DebugShape>>().iter(&world).count();
+        let outline = world.resource::<DebugOutlines>().0[&enemy];
+
+        world.get_mut::<Transform>(enemy).unwrap().translation.x = 40.;
+        stage.run(&mut world);
+        assert_eq!(world.query_filtered::<(), With<DebugShape>>().iter(&world).count(), shapes);
+        assert_eq!(world.resource::<DebugOutlines>().0[&enemy], outline);
+        assert_eq!(world.get::<Transform>(outline).unwrap().translation.x, 40.);
+
+        world.despawn(enemy);
+        stage.run(&mut world);
+        assert!(world.get_entity(outline).is_none());
+        assert_eq!(world.resource::<DebugOutlines>().0.len(), 1);
+    }
+
+    #[test]
+    fn hiding_the_overlay_removes_every_outline() {
+        let (mut world, mut stage) = outline_world();
+        world.spawn((Player::default(), Transform::default()));
+
+        stage.run(&mut world);
+        assert!(!world.resource::<DebugOutlines>().0.is_empty());
+        world.resource_mut::<DebugOverlay>().toggle();
+        stage.run(&mut world);
+
+        assert!(world.resource::<DebugOutlines>().0.is_empty());
+        // only the wall line is left, hidden
+        assert_eq!(world.query_filtered::<(), With<DebugShape>>().iter(&world).count(), 1);
+        let visibility = world.query_filtered::<&Visibility, With<WallLine>>().single(&world);
+        assert!(!visibility.is_visible);
+    }
+}
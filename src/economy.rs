@@ -0,0 +1,380 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::boss::Boss;
+use crate::commons::AppState;
+use crate::enemy::{EnemyKilled, EnemyKind, EnemyState, FromWave, Wave};
+use crate::profile::Profile;
+use crate::wall::WallDamaged;
+
+pub struct EconomyPlugin;
+
+impl Plugin for EconomyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Wallet>()
+            .init_resource::<WaveBonus>()
+            .add_system_set(SystemSet::on_enter(AppState::Playing).with_system(reset_economy))
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(pay_bounties)
+                    .with_system(track_wall_damage)
+                    .with_system(pay_wave_clears.after(track_wall_damage)),
+            );
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Income {
+    Bounty,
+    Loot,
+    WaveClear,
+    Interest,
+    Streak,
+    // handed out from the developer console
+    Cheat,
+}
+
+impl Income {
+    pub const ALL: [Income; 6] = [
+        Income::Bounty,
+        Income::Loot,
+        Income::WaveClear,
+        Income::Interest,
+        Income::Streak,
+        Income::Cheat,
+    ];
+
+    pub fn name(&self) -> &str {
+        match self {
+            Income::Bounty => "bounties",
+            Income::Loot => "loot",
+            Income::WaveClear => "wave clears",
+            Income::Interest => "interest",
+            Income::Streak => "streaks",
+            Income::Cheat => "cheats",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Expense {
+    Repair,
+    Upgrade,
+    Potion,
+    Pattern,
+    WallModule,
+    Trap,
+}
+
+impl Expense {
+    pub const ALL: [Expense; 6] = [
+        Expense::Repair,
+        Expense::Upgrade,
+        Expense::Potion,
+        Expense::Pattern,
+        Expense::WallModule,
+        Expense::Trap,
+    ];
+
+    pub fn name(&self) -> &str {
+        match self {
+            Expense::Repair => "repairs",
+            Expense::Upgrade => "upgrades",
+            Expense::Potion => "potions",
+            Expense::Pattern => "patterns",
+            Expense::WallModule => "wall modules",
+            Expense::Trap => "traps",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum Transaction {
+    Earned(Income, usize),
+    Spent(Expense, usize),
+}
+
+#[derive(Debug)]
+pub struct InsufficientFunds {
+    pub cost: usize,
+    pub balance: usize,
+}
+
+impl fmt::Display for InsufficientFunds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "costs ${} but only ${} banked", self.cost, self.balance)
+    }
+}
+
+// All the gold of a run, and a record of where it came from and went
+#[derive(Resource, Clone, Default, Serialize, Deserialize)]
+pub struct Wallet {
+    balance: usize,
+    ledger: Vec<Transaction>,
+}
+
+impl Wallet {
+    pub fn with_balance(balance: usize) -> Self {
+        Self {
+            balance,
+            ledger: Vec::new(),
+        }
+    }
+
+    pub fn balance(&self) -> usize {
+        self.balance
+    }
+
+    pub fn can_afford(&self, cost: usize) -> bool {
+        self.balance >= cost
+    }
+
+    pub fn earn(&mut self, income: Income, amount: usize) {
+        if amount == 0 {
+            return;
+        }
+
+        self.balance += amount;
+        self.ledger.push(Transaction::Earned(income, amount));
+    }
+
+    // Nothing is taken unless the whole cost can be paid
+    pub fn try_spend(&mut self, expense: Expense, cost: usize) -> Result<(), InsufficientFunds> {
+        if !self.can_afford(cost) {
+            return Err(InsufficientFunds {
+                cost,
+                balance: self.balance,
+            });
+        }
+
+        self.balance -= cost;
+        self.ledger.push(Transaction::Spent(expense, cost));
+        Ok(())
+    }
+
+    pub fn earned_from(&self, income: Income) -> usize {
+        self.ledger
+            .iter()
+            .map(|transaction| match transaction {
+                Transaction::Earned(from, amount) if *from == income => *amount,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    pub fn spent_on(&self, expense: Expense) -> usize {
+        self.ledger
+            .iter()
+            .map(|transaction| match transaction {
+                Transaction::Spent(on, amount) if *on == expense => *amount,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    pub fn total_earned(&self) -> usize {
+        Income::ALL.iter().map(|income| self.earned_from(*income)).sum()
+    }
+
+    pub fn total_spent(&self) -> usize {
+        Expense::ALL.iter().map(|expense| self.spent_on(*expense)).sum()
+    }
+}
+
+fn bounty(kind: EnemyKind) -> usize {
+    match kind {
+        EnemyKind::Grunt => 5,
+        EnemyKind::Flyer | EnemyKind::Burrower => 8,
+        EnemyKind::Leaper => 10,
+        EnemyKind::Boss => Boss::BOUNTY,
+    }
+}
+
+// Pays out for each wave once every enemy it sent is dead or gone
+#[derive(Resource, Default)]
+pub struct WaveBonus {
+    // the oldest wave that hasn't been paid for yet
+    next_wave: usize,
+    // waves cleared in a row without the wall taking a hit
+    streak: usize,
+    wall_hit: bool,
+}
+
+impl WaveBonus {
+    const CLEAR_BONUS: usize = 50;
+    const CLEAR_BONUS_PER_WAVE: usize = 25;
+    // paid on the gold banked when a wave is cleared
+    const INTEREST_RATE: f32 = 0.05;
+    const INTEREST_CAP: usize = 100;
+    const STREAK_BONUS: usize = 40;
+    const STREAK_CAP: usize = 5;
+
+    // Waves before `wave` are left unpaid, their enemies can't be tracked after a load
+    pub fn from_wave(wave: usize) -> Self {
+        Self {
+            next_wave: wave,
+            ..Default::default()
+        }
+    }
+
+    // Pay out for clearing `next_wave` and move on to the one after it
+    fn pay(&mut self, wallet: &mut Wallet) {
+        // interest is worked out before the clear bonus lands
+        let interest = ((wallet.balance() as f32 * Self::INTEREST_RATE) as usize).min(Self::INTEREST_CAP);
+        wallet.earn(Income::Interest, interest);
+        wallet.earn(Income::WaveClear, Self::CLEAR_BONUS + Self::CLEAR_BONUS_PER_WAVE * self.next_wave);
+
+        if self.wall_hit {
+            self.streak = 0;
+        } else {
+            self.streak = (self.streak + 1).min(Self::STREAK_CAP);
+            wallet.earn(Income::Streak, Self::STREAK_BONUS * self.streak);
+        }
+
+        self.wall_hit = false;
+        self.next_wave += 1;
+    }
+}
+
+fn reset_economy(mut commands: Commands, profile: Res<Profile>) {
+    commands.insert_resource(Wallet::with_balance(profile.starting_wealth()));
+    commands.insert_resource(WaveBonus::default());
+}
+
+fn pay_bounties(mut events: EventReader<EnemyKilled>, mut wallet: ResMut<Wallet>) {
+    for event in events.iter() {
+        wallet.earn(Income::Bounty, bounty(event.kind));
+    }
+}
+
+fn track_wall_damage(mut events: EventReader<WallDamaged>, mut bonus: ResMut<WaveBonus>) {
+    if events.iter().count() > 0 {
+        bonus.wall_hit = true;
+    }
+}
+
+fn pay_wave_clears(
+    query: Query<(&FromWave, &EnemyState)>,
+    wave: Res<Wave>,
+    mut bonus: ResMut<WaveBonus>,
+    mut wallet: ResMut<Wallet>,
+) {
+    // a wave still spawning can't be cleared yet
+    if bonus.next_wave >= wave.index() {
+        return;
+    }
+
+    let outstanding = query
+        .iter()
+        .any(|(from, state)| from.0 <= bonus.next_wave && *state != EnemyState::Death);
+    if outstanding {
+        return;
+    }
+
+    bonus.pay(&mut wallet);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overdraft_leaves_the_wallet_alone() {
+        let mut wallet = Wallet::with_balance(50);
+
+        let err = wallet.try_spend(Expense::Repair, 80).unwrap_err();
+        assert_eq!(err.cost, 80);
+        assert_eq!(err.balance, 50);
+        assert_eq!(wallet.balance(), 50);
+        assert!(wallet.ledger.is_empty());
+    }
+
+    #[test]
+    fn spending_everything_is_allowed() {
+        let mut wallet = Wallet::with_balance(50);
+
+        assert!(wallet.try_spend(Expense::Trap, 50).is_ok());
+        assert_eq!(wallet.balance(), 0);
+        assert_eq!(wallet.spent_on(Expense::Trap), 50);
+    }
+
+    #[test]
+    fn earning_nothing_is_not_recorded() {
+        let mut wallet = Wallet::default();
+
+        wallet.earn(Income::Interest, 0);
+        assert_eq!(wallet.balance(), 0);
+        assert!(wallet.ledger.is_empty());
+    }
+
+    #[test]
+    fn totals_add_up_the_ledger() {
+        let mut wallet = Wallet::with_balance(100);
+        wallet.earn(Income::Bounty, 5);
+        wallet.earn(Income::Bounty, 8);
+        wallet.earn(Income::Loot, 10);
+        wallet.try_spend(Expense::Repair, 30).unwrap();
+        wallet.try_spend(Expense::Potion, 20).unwrap();
+        wallet.try_spend(Expense::Repair, 30).unwrap();
+
+        assert_eq!(wallet.earned_from(Income::Bounty), 13);
+        assert_eq!(wallet.total_earned(), 23);
+        assert_eq!(wallet.spent_on(Expense::Repair), 60);
+        assert_eq!(wallet.total_spent(), 80);
+        // the starting balance isn't income
+        assert_eq!(wallet.balance(), 100 + 23 - 80);
+    }
+
+    #[test]
+    fn interest_is_capped() {
+        let mut wallet = Wallet::with_balance(1000);
+        WaveBonus::default().pay(&mut wallet);
+        assert_eq!(wallet.earned_from(Income::Interest), 50);
+
+        let mut wallet = Wallet::with_balance(100_000);
+        WaveBonus::default().pay(&mut wallet);
+        assert_eq!(wallet.earned_from(Income::Interest), WaveBonus::INTEREST_CAP);
+    }
+
+    #[test]
+    fn clear_bonus_grows_with_the_wave() {
+        let mut wallet = Wallet::default();
+        let mut bonus = WaveBonus::from_wave(2);
+        bonus.pay(&mut wallet);
+
+        assert_eq!(wallet.earned_from(Income::WaveClear), 50 + 25 * 2);
+        assert_eq!(bonus.next_wave, 3);
+    }
+
+    #[test]
+    fn a_wall_hit_resets_the_streak() {
+        let mut wallet = Wallet::default();
+        let mut bonus = WaveBonus::default();
+
+        bonus.pay(&mut wallet);
+        bonus.pay(&mut wallet);
+        assert_eq!(bonus.streak, 2);
+        assert_eq!(wallet.earned_from(Income::Streak), 40 + 80);
+
+        bonus.wall_hit = true;
+        bonus.pay(&mut wallet);
+        assert_eq!(bonus.streak, 0);
+        assert_eq!(wallet.earned_from(Income::Streak), 40 + 80);
+        assert!(!bonus.wall_hit);
+
+        bonus.pay(&mut wallet);
+        assert_eq!(bonus.streak, 1);
+    }
+
+    #[test]
+    fn streak_is_capped() {
+        let mut wallet = Wallet::default();
+        let mut bonus = WaveBonus::default();
+
+        for _ in 0..WaveBonus::STREAK_CAP + 3 {
+            bonus.pay(&mut wallet);
+        }
+        assert_eq!(bonus.streak, WaveBonus::STREAK_CAP);
+    }
+}
@@ -1,56 +1,197 @@
 use bevy::prelude::*;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 use std::default::Default;
 
-use crate::commons::GameTextures;
-use crate::wall::Wall;
+use crate::ai::Brain;
+use crate::buffs::PlayerStats;
+use crate::boss::{spawn_boss, Boss};
+use crate::commons::{AppState, GameRng, GameTextures};
+use crate::damage::Resistances;
+use crate::debug::timed;
+use crate::player::{Player, PlayerHurt};
+use crate::pool::EntityPool;
+use crate::stats::RunStats;
+use crate::traps::TrapStruck;
+use crate::wall::{Wall, WallDamaged};
 
 pub struct EnemyPlugin;
 
 impl Plugin for EnemyPlugin {
     fn build(&self, app: &mut App) {
         app
-            .add_system(spawn_enemy)
-            .add_system(movement)
-            .add_system(animate_enemy_sprite)
-            .add_system(state_transitions);
+            .init_resource::<Wave>()
+            .init_resource::<EntityPool<Enemy>>()
+            .add_event::<EnemyKilled>()
+            .add_system_set(SystemSet::on_enter(AppState::Playing).with_system(reset_enemies))
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(timed(spawn_enemy))
+                    .with_system(timed(animate_enemy_sprite))
+                    .with_system(breach)
+                    .with_system(thaw),
+            );
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum EnemyKind {
+    #[default]
+    Grunt,
+    Boss,
+    Flyer,
+    Burrower,
+    Leaper,
+}
+
+impl EnemyKind {
+    pub fn name(&self) -> &str {
+        match self {
+            EnemyKind::Grunt => "Grunt",
+            EnemyKind::Boss => "Warlord",
+            EnemyKind::Flyer => "Bat",
+            EnemyKind::Burrower => "Sapper",
+            EnemyKind::Leaper => "Jumper",
+        }
+    }
+
+    pub fn size(&self) -> Vec2 {
+        match self {
+            EnemyKind::Grunt | EnemyKind::Burrower | EnemyKind::Leaper => Vec2::new(57.5, 82.5),
+            EnemyKind::Boss => Vec2::new(172.5, 247.5),
+            EnemyKind::Flyer => Vec2::new(43., 62.),
+        }
+    }
+
+    // damage dealt to the wall each time an attack animation lands
+    pub fn wall_damage(&self) -> f32 {
+        match self {
+            EnemyKind::Grunt | EnemyKind::Burrower | EnemyKind::Leaper => 10.,
+            EnemyKind::Boss => 40.,
+            EnemyKind::Flyer => 5.,
+        }
+    }
+
+    // (health, speed) for a freshly spawned enemy of this kind
+    fn stats(&self) -> (f32, f32) {
+        match self {
+            EnemyKind::Grunt => (100., 50.),
+            EnemyKind::Boss => (1000., 25.),
+            EnemyKind::Flyer => (40., 90.),
+            EnemyKind::Burrower => (80., 45.),
+            EnemyKind::Leaper => (70., 60.),
+        }
     }
 }
 
 #[derive(Component)]
 pub struct Enemy {
+    kind: EnemyKind,
     speed: f32,
     health: f32,
+    max_health: f32,
 }
 
 impl Enemy {
+    pub fn new(kind: EnemyKind) -> Self {
+        let (health, speed) = kind.stats();
+
+        Self {
+            kind,
+            speed,
+            health,
+            max_health: health,
+        }
+    }
+
+    pub fn boss(max_health: f32) -> Self {
+        Self::with_max_health(EnemyKind::Boss, max_health)
+    }
+
+    pub fn with_max_health(kind: EnemyKind, max_health: f32) -> Self {
+        Self {
+            health: max_health,
+            max_health,
+            ..Self::new(kind)
+        }
+    }
+
     pub fn apply_damage(&mut self, damage: f32) {
         self.health -= damage;
     }
 
-    pub fn get_hurtbox() -> Vec2 {
-        Vec2::new(57.5, 82.5)
+    pub fn heal(&mut self, amount: f32) {
+        self.health = (self.health + amount).min(self.max_health);
+    }
+
+    pub fn kind(&self) -> EnemyKind {
+        self.kind
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
     }
 
-    fn health(&self) -> f32 {
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    pub fn hurtbox(&self) -> Vec2 {
+        self.kind.size()
+    }
+
+    pub fn health(&self) -> f32 {
         self.health
     }
+
+    pub fn max_health(&self) -> f32 {
+        self.max_health
+    }
 }
 
 impl Default for Enemy {
     fn default() -> Self {
-        Self {
-            speed: 50.,
-            health: 100.,
-        }
+        Self::new(EnemyKind::Grunt)
     }
 }
 
-#[derive(Component)]
+#[derive(Component, Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub enum EnemyState {
     Walk,
     Death,
     Attack,
+    // holding position until enough allies catch up
+    Wait,
+    // running back the way it came
+    Flee,
+    // mid-air on the way over a damaged wall
+    Leap,
+}
+
+// How high up an enemy is right now, which decides what can hit it
+#[derive(Component, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Elevation {
+    #[default]
+    Ground,
+    Air,
+    Underground,
+}
+
+// The wizard's column, enemies that make it this far have breached the defenses
+pub const BREACH_X: f32 = 700.;
+
+// Slows an enemy down until the timer runs out
+#[derive(Component, Deref, DerefMut)]
+pub struct Chilled(pub Timer);
+
+impl Chilled {
+    pub const SLOW: f32 = 0.5;
+    pub const TINT: Color = Color::rgb(0.5, 0.8, 1.);
+}
+
+pub struct EnemyKilled {
+    pub kind: EnemyKind,
+    pub position: Vec2,
 }
 
 #[derive(Resource, Deref, DerefMut)]
@@ -59,65 +200,221 @@ pub struct EnemySpawnTimer(pub Timer);
 impl EnemySpawnTimer {
 }
 
+// The wave an enemy was sent in, so the economy can tell when a wave is cleared
+#[derive(Component)]
+pub struct FromWave(pub usize);
+
+#[derive(Resource, Default, Clone, Serialize, Deserialize)]
+pub struct Wave {
+    index: usize,
+    spawned: usize,
+}
+
+impl Wave {
+    pub const SIZE: usize = 10;
+    // every fifth wave opens with a boss
+    pub const BOSS_EVERY: usize = 5;
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn is_boss_wave(&self) -> bool {
+        (self.index + 1).is_multiple_of(Self::BOSS_EVERY)
+    }
+
+    // Grunts always come, the other kinds join the mix as the waves go on
+    fn roll_kind(&self, rng: &mut impl Rng) -> EnemyKind {
+        let roster = [
+            (EnemyKind::Grunt, 0, 6),
+            (EnemyKind::Flyer, 1, 2),
+            (EnemyKind::Burrower, 2, 2),
+            (EnemyKind::Leaper, 3, 2),
+        ];
+
+        let unlocked = roster.iter().filter(|(_, from, _)| self.index >= *from);
+        let total: u32 = unlocked.clone().map(|(_, _, weight)| weight).sum();
+        let mut roll = rng.gen_range(0..total);
+
+        for (kind, _, weight) in unlocked {
+            if roll < *weight {
+                return *kind;
+            }
+            roll -= weight;
+        }
+
+        EnemyKind::Grunt
+    }
+
+    // 1 for the first boss wave, 2 for the second and so on
+    pub fn boss_number(&self) -> usize {
+        (self.index + 1) / Self::BOSS_EVERY
+    }
+
+    // Move straight on to the next wave, whatever's left of this one stays unspawned
+    pub fn skip(&mut self) {
+        self.index += 1;
+        self.spawned = 0;
+    }
+
+    fn record_spawn(&mut self) {
+        self.spawned += 1;
+
+        if self.spawned >= Self::SIZE {
+            self.index += 1;
+            self.spawned = 0;
+        }
+    }
+}
+
+pub fn spawn_enemy_at(
+    commands: &mut Commands,
+    pool: &mut EntityPool<Enemy>,
+    game_textures: &GameTextures,
+    position: Vec2,
+    enemy: Enemy,
+    state: EnemyState,
+) -> Entity {
+    pool.spawn(commands, (
+        SpriteSheetBundle {
+            texture_atlas: game_textures.enemy_walk.clone(),
+            sprite: TextureAtlasSprite {
+                custom_size: Some(enemy.kind().size()),
+                ..default()
+            },
+            transform: Transform {
+                translation: position.extend(100.),
+                ..default()
+            },
+            ..default()
+        },
+        AnimationTimer(Timer::from_seconds(0.1, TimerMode::Repeating)),
+    ))
+    .insert(Resistances::for_kind(enemy.kind()))
+    .insert(enemy)
+    .insert(state)
+    .id()
+}
+
+// Hand a dead or cleared enemy back to the pool for the next spawn
+pub fn release_enemy(commands: &mut Commands, pool: &mut EntityPool<Enemy>, entity: Entity) {
+    commands
+        .entity(entity)
+        .remove::<(Enemy, EnemyState, Elevation, Resistances, Chilled, Boss, Brain, FromWave)>();
+    pool.release(commands, entity);
+}
+
 fn spawn_enemy(
     mut commands: Commands,
+    mut pool: ResMut<EntityPool<Enemy>>,
     game_textures: Res<GameTextures>,
     mut spawn_timer: ResMut<EnemySpawnTimer>,
+    mut wave: ResMut<Wave>,
+    mut game_rng: ResMut<GameRng>,
     time: Res<Time>,
 ) {
     if spawn_timer.finished() {
         // pick random y
-        let y = rand::thread_rng().gen_range(-385..385) as f32;
-        // spawn enemy
-        commands.spawn((
-            SpriteSheetBundle {
-                texture_atlas: game_textures.enemy_walk.clone(),
-                sprite: TextureAtlasSprite {
-                    custom_size: Some(Vec2::new(57.5, 82.5)),
-                    ..default()
-                },
-                transform: Transform {
-                    translation: Vec3::new(-800., y, 100.),
-                    ..default()
-                },
-                ..default()
-            },
-            AnimationTimer(Timer::from_seconds(0.1, TimerMode::Repeating)),
-        ))
-        .insert(Enemy::default())
-        .insert(EnemyState::Walk);
+        let y = game_rng.rng().gen_range(-385..385) as f32;
+        // spawn enemy, boss waves lead with their boss
+        let entity = if wave.is_boss_wave() && wave.spawned == 0 {
+            spawn_boss(
+                &mut commands,
+                &mut pool,
+                &game_textures,
+                Vec2::new(-800., y.clamp(-260., 260.)),
+                Enemy::boss(Boss::max_health(wave.boss_number())),
+            )
+        } else {
+            let kind = wave.roll_kind(game_rng.rng());
+            spawn_enemy_at(
+                &mut commands,
+                &mut pool,
+                &game_textures,
+                Vec2::new(-800., y),
+                Enemy::new(kind),
+                EnemyState::Walk,
+            )
+        };
+
+        commands.entity(entity).insert(FromWave(wave.index()));
+        wave.record_spawn();
     }
 
     spawn_timer.tick(time.delta());
 }
 
-// move based on state
-fn movement(
-    mut query: Query<(&Enemy, &EnemyState, &mut Transform)>,
-    time: Res<Time>,
+// Clear out the last run so a new one starts from an empty field
+fn reset_enemies(
+    mut commands: Commands,
+    mut pool: ResMut<EntityPool<Enemy>>,
+    query: Query<Entity, With<Enemy>>,
+    mut spawn_timer: ResMut<EnemySpawnTimer>,
 ) {
-    for (enemy, enemy_state, mut transform) in query.iter_mut() {
-        let dx = match enemy_state {
-            EnemyState::Walk => {
-                enemy.speed * time.delta_seconds()
-            },
-            _ => 0.
-        };
-        transform.translation.x += dx;
+    for entity in query.iter() {
+        release_enemy(&mut commands, &mut pool, entity);
     }
+
+    commands.insert_resource(Wave::default());
+    spawn_timer.reset();
 }
 
-// logic for changing state based on current state
-fn state_transitions(
-    mut query: Query<(Entity, &mut EnemyState, &mut Enemy, &Transform)>,
+// Anything that gets past the wall and reaches the wizard's column hits the
+// keep behind it and is gone
+#[allow(clippy::too_many_arguments)]
+fn breach(
+    mut commands: Commands,
+    mut pool: ResMut<EntityPool<Enemy>>,
+    query: Query<(Entity, &Enemy, &EnemyState, &Transform)>,
+    player_query: Query<(&Player, &Transform)>,
+    mut wall: ResMut<Wall>,
+    player_stats: Res<PlayerStats>,
+    mut stats: ResMut<RunStats>,
+    mut wall_damaged: EventWriter<WallDamaged>,
+    mut player_hurt: EventWriter<PlayerHurt>,
 ) {
-    for (_enemy_entity, mut enemy_state, enemy, transform) in query.iter_mut() {
-        if transform.translation.x >= Wall::LEFT - Wall::SIZE {
-            *enemy_state = EnemyState::Attack;
+    let (player, player_transform) = player_query.single();
+
+    for (entity, enemy, state, transform) in query.iter() {
+        if *state == EnemyState::Death || transform.translation.x < BREACH_X {
+            continue;
+        }
+
+        // whatever gets through lands on the wizard if they're standing in its lane
+        let reach = (enemy.hurtbox().y + player.size().y) / 2.;
+        if (transform.translation.y - player_transform.translation.y).abs() < reach {
+            player_hurt.send(PlayerHurt {
+                amount: enemy.kind().wall_damage(),
+                from: transform.translation.truncate(),
+            });
         }
 
-        if enemy.health() <= 0. {
-            *enemy_state = EnemyState::Death;
+        // wall buffs soften what gets through, or stop it entirely
+        let damage = enemy.kind().wall_damage() * (1. - player_stats.wall_damage_reduction());
+        if damage > 0. {
+            stats.wall_damage_taken += wall.apply_damage(damage);
+            wall_damaged.send(WallDamaged {
+                amount: damage,
+                position: transform.translation.truncate(),
+                attacker: None,
+            });
+        }
+
+        release_enemy(&mut commands, &mut pool, entity);
+    }
+}
+
+fn thaw(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Chilled, &mut TextureAtlasSprite)>,
+    time: Res<Time>,
+) {
+    for (entity, mut chilled, mut sprite) in query.iter_mut() {
+        chilled.tick(time.delta());
+
+        if chilled.finished() {
+            sprite.color = Color::WHITE;
+            commands.entity(entity).remove::<Chilled>();
         }
     }
 }
@@ -126,19 +423,25 @@ fn state_transitions(
 #[derive(Component, Deref, DerefMut)]
 struct AnimationTimer(Timer);
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn animate_enemy_sprite(
     mut commands: Commands,
     time: Res<Time>,
     texture_atlases: Res<Assets<TextureAtlas>>,
-    mut query: Query<(Entity, &EnemyState, &mut AnimationTimer, &mut TextureAtlasSprite, &mut Handle<TextureAtlas>)>,
+    mut query: Query<(Entity, &Enemy, &EnemyState, Option<&Brain>, &Transform, &mut AnimationTimer, &mut TextureAtlasSprite, &mut Handle<TextureAtlas>)>,
     game_textures: Res<GameTextures>,
     mut wall: ResMut<Wall>,
+    player_stats: Res<PlayerStats>,
+    mut stats: ResMut<RunStats>,
+    mut wall_damaged: EventWriter<WallDamaged>,
+    mut barricade_struck: EventWriter<TrapStruck>,
+    mut pool: ResMut<EntityPool<Enemy>>,
 ) {
-    for (entity, enemy_state, mut timer, mut sprite, mut texture_atlas_handle) in &mut query {
+    for (entity, enemy, enemy_state, brain, transform, mut timer, mut sprite, mut texture_atlas_handle) in &mut query {
         timer.tick(time.delta());
         // Set enemy animations based on state
         match enemy_state {
-            EnemyState::Walk => {
+            EnemyState::Walk | EnemyState::Flee | EnemyState::Leap => {
                 if sprite.index >= 12 {
                     sprite.index = 0;
                 }
@@ -150,6 +453,18 @@ fn animate_enemy_sprite(
                     sprite.index = (sprite.index + 1) % texture_atlas.textures.len();
                 }
             },
+            EnemyState::Wait => {
+                if sprite.index >= 14 {
+                    sprite.index = 0;
+                }
+                let atlas = game_textures.enemy_idle.clone();
+                *texture_atlas_handle = atlas;
+
+                if timer.just_finished() {
+                    let texture_atlas = texture_atlases.get(&texture_atlas_handle).unwrap();
+                    sprite.index = (sprite.index + 1) % texture_atlas.textures.len();
+                }
+            },
             EnemyState::Death => {
                 if sprite.index >= 10 {
                     sprite.index = 0;
@@ -159,7 +474,7 @@ fn animate_enemy_sprite(
 
                 if timer.just_finished() {
                     if sprite.index >= 9 {
-                        commands.entity(entity).despawn();
+                        release_enemy(&mut commands, &mut pool, entity);
                     } else {
                         sprite.index += 1;
                     }
@@ -168,7 +483,25 @@ fn animate_enemy_sprite(
             EnemyState::Attack => {
                 if sprite.index >= 17 {
                     sprite.index = 0;
-                    wall.apply_damage(10.);
+
+                    // a barricade in the way soaks the blows before the wall does
+                    if let Some(barricade) = brain.and_then(|brain| brain.blocked_by()) {
+                        barricade_struck.send(TrapStruck {
+                            trap: barricade,
+                            amount: enemy.kind().wall_damage(),
+                        });
+                    } else {
+                        // wall buffs soften the blow, or stop it entirely
+                        let damage = enemy.kind().wall_damage() * (1. - player_stats.wall_damage_reduction());
+                        if damage > 0. {
+                            stats.wall_damage_taken += wall.apply_damage(damage);
+                            wall_damaged.send(WallDamaged {
+                                amount: damage,
+                                position: Vec2::new(Wall::LEFT - Wall::SIZE / 2., transform.translation.y),
+                                attacker: Some(entity),
+                            });
+                        }
+                    }
                 }
                 
                 let atlas = game_textures.enemy_attack.clone();
@@ -0,0 +1,194 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::damage::{DamageDealt, DamageKind, DamageStyle};
+use crate::enemy::Chilled;
+use crate::wall::WallDamaged;
+
+pub struct FeedbackPlugin;
+
+impl Plugin for FeedbackPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ScreenShake>()
+            .add_system(spawn_damage_numbers)
+            .add_system(float_damage_numbers)
+            .add_system(flash_on_hit)
+            .add_system(fade_flash)
+            .add_system(shake_on_wall_damage)
+            .add_system(shake_camera);
+    }
+}
+
+// Combat text that drifts up from where a hit landed and fades out
+#[derive(Component)]
+struct DamageNumber {
+    timer: Timer,
+    color: Color,
+}
+
+impl DamageKind {
+    fn color(&self) -> Color {
+        match self {
+            DamageKind::Fire => Color::rgb(1., 0.55, 0.2),
+            DamageKind::Frost => Color::rgb(0.5, 0.8, 1.),
+            DamageKind::Lightning => Color::rgb(0.95, 0.95, 0.4),
+            DamageKind::Arcane => Color::rgb(0.8, 0.4, 1.),
+            DamageKind::Physical => Color::WHITE,
+        }
+    }
+}
+
+impl DamageStyle {
+    // plain hits take the colour of their damage kind
+    fn color(&self, kind: DamageKind) -> Color {
+        match self {
+            DamageStyle::Normal => kind.color(),
+            DamageStyle::Crit => Color::rgb(1., 0.8, 0.1),
+            DamageStyle::Status => Color::rgb(0.6, 1., 0.4),
+        }
+    }
+
+    fn font_size(&self) -> f32 {
+        match self {
+            DamageStyle::Normal => 24.,
+            DamageStyle::Crit => 36.,
+            DamageStyle::Status => 20.,
+        }
+    }
+}
+
+// Brief white flash on an enemy that was just struck
+#[derive(Component, Deref, DerefMut)]
+struct Flash(Timer);
+
+// Camera shake, decays back to zero on its own
+#[derive(Resource, Default)]
+pub struct ScreenShake {
+    trauma: f32,
+}
+
+impl ScreenShake {
+    const MAX_OFFSET: f32 = 12.;
+    const DECAY: f32 = 2.;
+
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).min(1.);
+    }
+}
+
+fn spawn_damage_numbers(
+    mut commands: Commands,
+    mut events: EventReader<DamageDealt>,
+    asset_server: Res<AssetServer>,
+) {
+    let font: Handle<Font> = asset_server.load("font.ttf");
+
+    for event in events.iter() {
+        // spread numbers out a little so rapid hits don't stack on top of each other
+        let jitter = rand::thread_rng().gen_range(-15. ..15.);
+
+        commands
+            .spawn(Text2dBundle {
+                text: Text::from_section(
+                    format!("{:.0}", event.amount),
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: event.style.font_size(),
+                        color: event.style.color(event.kind),
+                    },
+                )
+                .with_alignment(TextAlignment::CENTER),
+                transform: Transform {
+                    translation: Vec3::new(event.position.x + jitter, event.position.y + 40., 150.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(DamageNumber {
+                timer: Timer::from_seconds(0.8, TimerMode::Once),
+                color: event.style.color(event.kind),
+            });
+    }
+}
+
+fn float_damage_numbers(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut DamageNumber, &mut Transform, &mut Text)>,
+    time: Res<Time>,
+) {
+    for (entity, mut number, mut transform, mut text) in query.iter_mut() {
+        number.timer.tick(time.delta());
+
+        transform.translation.y += 60. * time.delta_seconds();
+        let mut color = number.color;
+        color.set_a(1. - number.timer.percent());
+        text.sections[0].style.color = color;
+
+        if number.timer.finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn flash_on_hit(
+    mut commands: Commands,
+    mut events: EventReader<DamageDealt>,
+    mut query: Query<&mut TextureAtlasSprite>,
+) {
+    for event in events.iter() {
+        if let Ok(mut sprite) = query.get_mut(event.target) {
+            // tint values above 1 push every lit pixel towards white
+            sprite.color = Color::rgb(8., 8., 8.);
+            commands
+                .entity(event.target)
+                .insert(Flash(Timer::from_seconds(0.08, TimerMode::Once)));
+        }
+    }
+}
+
+fn fade_flash(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Flash, &mut TextureAtlasSprite, Option<&Chilled>)>,
+    time: Res<Time>,
+) {
+    for (entity, mut flash, mut sprite, chilled) in query.iter_mut() {
+        flash.tick(time.delta());
+
+        if flash.finished() {
+            sprite.color = match chilled {
+                Some(_) => Chilled::TINT,
+                None => Color::WHITE,
+            };
+            commands.entity(entity).remove::<Flash>();
+        }
+    }
+}
+
+fn shake_on_wall_damage(mut events: EventReader<WallDamaged>, mut shake: ResMut<ScreenShake>) {
+    for event in events.iter() {
+        shake.add_trauma(event.amount / 40.);
+    }
+}
+
+fn shake_camera(
+    mut shake: ResMut<ScreenShake>,
+    mut camera_query: Query<&mut Transform, With<Camera>>,
+    time: Res<Time>,
+) {
+    let mut transform = camera_query.single_mut();
+
+    if shake.trauma <= 0. {
+        transform.translation.x = 0.;
+        transform.translation.y = 0.;
+        return;
+    }
+
+    // squaring trauma keeps small hits subtle while big ones still kick
+    let offset = ScreenShake::MAX_OFFSET * shake.trauma * shake.trauma;
+    // cosmetic only, so keep it off the seeded game rng
+    let mut rng = rand::thread_rng();
+    transform.translation.x = rng.gen_range(-1. ..1.) * offset;
+    transform.translation.y = rng.gen_range(-1. ..1.) * offset;
+
+    shake.trauma = (shake.trauma - ScreenShake::DECAY * time.delta_seconds()).max(0.);
+}
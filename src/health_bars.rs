@@ -0,0 +1,287 @@
+use bevy::{prelude::*, sprite::Anchor};
+
+use crate::boss::Boss;
+use crate::enemy::Enemy;
+use crate::wall::Wall;
+
+pub struct HealthBarPlugin;
+
+impl Plugin for HealthBarPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_wall_health_bar)
+            .add_system(attach_enemy_health_bars)
+            .add_system(update_enemy_health_bars)
+            .add_system(attach_boss_health_bars)
+            .add_system(update_boss_health_bars)
+            .add_system(update_wall_health_bar);
+    }
+}
+
+const ENEMY_BAR_SIZE: Vec2 = Vec2::new(50., 6.);
+const WALL_BAR_SIZE: Vec2 = Vec2::new(300., 20.);
+const BOSS_BAR_SIZE: Vec2 = Vec2::new(600., 24.);
+
+// Background of an enemy's bar, hidden while the enemy is unhurt
+#[derive(Component)]
+struct EnemyHealthBar;
+
+#[derive(Component)]
+struct EnemyHealthFill;
+
+#[derive(Component)]
+struct WallHealthFill;
+
+// Big bar across the top of the screen, one per living boss
+#[derive(Component)]
+struct BossHealthBar {
+    boss: Entity,
+}
+
+#[derive(Component)]
+struct BossHealthFill;
+
+// Trails behind the real value after a hit so the chunk lost stays visible for a moment
+#[derive(Component)]
+struct WallHealthGhost {
+    value: f32,
+    delay: Timer,
+}
+
+impl WallHealthGhost {
+    const DRAIN_PER_SECOND: f32 = 60.;
+}
+
+fn health_color(fraction: f32) -> Color {
+    if fraction > 0.6 {
+        Color::rgb(0.2, 0.8, 0.2)
+    } else if fraction > 0.3 {
+        Color::rgb(0.9, 0.8, 0.1)
+    } else {
+        Color::rgb(0.9, 0.15, 0.1)
+    }
+}
+
+fn attach_enemy_health_bars(
+    mut commands: Commands,
+    query: Query<(Entity, Option<&Children>), Added<Enemy>>,
+    bar_query: Query<(), With<EnemyHealthBar>>,
+) {
+    for (entity, children) in query.iter() {
+        // enemies coming back out of the pool still have their old bar
+        if let Some(children) = children {
+            if children.iter().any(|child| bar_query.contains(*child)) {
+                continue;
+            }
+        }
+
+        let bar = commands
+            .spawn(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(0., 0., 0., 0.7),
+                    custom_size: Some(ENEMY_BAR_SIZE),
+                    ..Default::default()
+                },
+                transform: Transform::from_xyz(0., 50., 1.),
+                visibility: Visibility { is_visible: false },
+                ..Default::default()
+            })
+            .insert(EnemyHealthBar)
+            .with_children(|parent| {
+                parent
+                    .spawn(SpriteBundle {
+                        sprite: Sprite {
+                            color: health_color(1.),
+                            custom_size: Some(ENEMY_BAR_SIZE),
+                            anchor: Anchor::CenterLeft,
+                            ..Default::default()
+                        },
+                        transform: Transform::from_xyz(-ENEMY_BAR_SIZE.x / 2., 0., 1.),
+                        ..Default::default()
+                    })
+                    .insert(EnemyHealthFill);
+            })
+            .id();
+
+        commands.entity(entity).add_child(bar);
+    }
+}
+
+fn update_enemy_health_bars(
+    enemy_query: Query<(&Enemy, &Children, Option<&Boss>), Changed<Enemy>>,
+    mut bar_query: Query<(&mut Visibility, &Children), With<EnemyHealthBar>>,
+    mut fill_query: Query<&mut Sprite, With<EnemyHealthFill>>,
+) {
+    for (enemy, children, boss) in enemy_query.iter() {
+        let fraction = (enemy.health() / enemy.max_health()).clamp(0., 1.);
+
+        for child in children.iter() {
+            if let Ok((mut visibility, bar_children)) = bar_query.get_mut(*child) {
+                // bosses get the big bar at the top instead
+                visibility.is_visible = boss.is_none() && fraction > 0. && fraction < 1.;
+
+                for fill in bar_children.iter() {
+                    if let Ok(mut sprite) = fill_query.get_mut(*fill) {
+                        sprite.custom_size = Some(Vec2::new(ENEMY_BAR_SIZE.x * fraction, ENEMY_BAR_SIZE.y));
+                        sprite.color = health_color(fraction);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn attach_boss_health_bars(
+    mut commands: Commands,
+    query: Query<(Entity, &Enemy), Added<Boss>>,
+    bar_query: Query<&BossHealthBar>,
+    asset_server: Res<AssetServer>,
+) {
+    let font: Handle<Font> = asset_server.load("font.ttf");
+    let existing = bar_query.iter().count();
+
+    for (i, (entity, enemy)) in query.iter().enumerate() {
+        let slot = (existing + i) as f32;
+
+        commands
+            .spawn(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        left: Val::Px(500.),
+                        top: Val::Px(20. + slot * (BOSS_BAR_SIZE.y + 16.)),
+                        ..default()
+                    },
+                    size: Size::new(Val::Px(BOSS_BAR_SIZE.x), Val::Px(BOSS_BAR_SIZE.y)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0., 0., 0., 0.7).into(),
+                ..default()
+            })
+            .insert(BossHealthBar { boss: entity })
+            .with_children(|parent| {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            position: UiRect {
+                                left: Val::Px(0.),
+                                top: Val::Px(0.),
+                                ..default()
+                            },
+                            size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                            ..default()
+                        },
+                        background_color: Color::rgb(0.6, 0.1, 0.6).into(),
+                        ..default()
+                    })
+                    .insert(BossHealthFill);
+                parent.spawn(TextBundle::from_section(
+                    enemy.kind().name(),
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 20.,
+                        color: Color::WHITE,
+                    },
+                ));
+            });
+    }
+}
+
+fn update_boss_health_bars(
+    mut commands: Commands,
+    bar_query: Query<(Entity, &BossHealthBar, &Children)>,
+    mut fill_query: Query<&mut Style, With<BossHealthFill>>,
+    boss_query: Query<&Enemy, With<Boss>>,
+) {
+    for (entity, bar, children) in bar_query.iter() {
+        let enemy = match boss_query.get(bar.boss) {
+            Ok(enemy) => enemy,
+            Err(_) => {
+                commands.entity(entity).despawn_recursive();
+                continue;
+            }
+        };
+
+        let fraction = (enemy.health() / enemy.max_health()).clamp(0., 1.);
+        for child in children.iter() {
+            if let Ok(mut style) = fill_query.get_mut(*child) {
+                style.size.width = Val::Percent(fraction * 100.);
+            }
+        }
+    }
+}
+
+fn spawn_wall_health_bar(mut commands: Commands) {
+    let bar_segment = |color: Color| NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+            ..default()
+        },
+        background_color: color.into(),
+        ..default()
+    };
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(50.),
+                    top: Val::Px(85.),
+                    ..default()
+                },
+                size: Size::new(Val::Px(WALL_BAR_SIZE.x), Val::Px(WALL_BAR_SIZE.y)),
+                ..default()
+            },
+            background_color: Color::rgba(0., 0., 0., 0.7).into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn(bar_segment(Color::rgba(1., 1., 1., 0.6)))
+                .insert(WallHealthGhost {
+                    value: 1.,
+                    delay: Timer::from_seconds(0.4, TimerMode::Once),
+                });
+            parent.spawn(bar_segment(health_color(1.))).insert(WallHealthFill);
+        });
+}
+
+#[allow(clippy::type_complexity)]
+fn update_wall_health_bar(
+    wall: Res<Wall>,
+    mut fill_query: Query<(&mut Style, &mut BackgroundColor), (With<WallHealthFill>, Without<WallHealthGhost>)>,
+    mut ghost_query: Query<(&mut Style, &mut WallHealthGhost), Without<WallHealthFill>>,
+    time: Res<Time>,
+) {
+    let fraction = (wall.health() / wall.max_health()).clamp(0., 1.);
+
+    for (mut style, mut color) in fill_query.iter_mut() {
+        style.size.width = Val::Percent(fraction * 100.);
+        *color = health_color(fraction).into();
+    }
+
+    for (mut style, mut ghost) in ghost_query.iter_mut() {
+        if fraction >= ghost.value {
+            // healing shows up straight away
+            ghost.value = fraction;
+            ghost.delay.reset();
+        } else {
+            // every fresh hit holds the ghost in place a little longer
+            if wall.is_changed() {
+                ghost.delay.reset();
+            }
+            ghost.delay.tick(time.delta());
+
+            if ghost.delay.finished() {
+                let drain = WallHealthGhost::DRAIN_PER_SECOND / wall.max_health() * time.delta_seconds();
+                ghost.value = (ghost.value - drain).max(fraction);
+            }
+        }
+
+        style.size.width = Val::Percent(ghost.value * 100.);
+    }
+}
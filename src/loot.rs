@@ -0,0 +1,240 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::TAU;
+
+use crate::ai::Archetypes;
+use crate::buffs::{Buff, Buffs, Modifier, Stat};
+use crate::commons::{AppState, GameRng};
+use crate::economy::{Income, Wallet};
+use crate::enemy::EnemyKilled;
+use crate::player::{Caster, Player};
+
+pub struct LootPlugin;
+
+impl Plugin for LootPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<LootCollected>()
+            .add_system_set(SystemSet::on_enter(AppState::Playing).with_system(reset_loot))
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(drop_loot)
+                    .with_system(move_pickups)
+                    .with_system(collect_loot),
+            );
+    }
+}
+
+// Temporary boosts dropped by enemies, each one a buff on the wizard once picked up
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PowerUp {
+    DoubleDamage,
+    RapidFire,
+    WallShield,
+    Multishot,
+    Haste,
+}
+
+impl PowerUp {
+    fn color(&self) -> Color {
+        match self {
+            PowerUp::DoubleDamage => Color::rgb(1., 0.3, 0.2),
+            PowerUp::RapidFire => Color::rgb(1., 1., 0.5),
+            PowerUp::WallShield => Color::rgb(0.4, 1., 1.),
+            PowerUp::Multishot => Color::rgb(0.8, 0.4, 1.),
+            PowerUp::Haste => Color::rgb(0.5, 1., 0.4),
+        }
+    }
+
+    fn buff(&self) -> Buff {
+        match self {
+            PowerUp::DoubleDamage => Buff::new("Double Damage", "DMG", self.color(), 10.)
+                .with(Stat::ProjectileDamage, Modifier::Multiply(2.)),
+            PowerUp::RapidFire => Buff::new("Rapid Fire", "RATE", self.color(), 8.)
+                .with(Stat::FireRate, Modifier::Multiply(2.)),
+            PowerUp::WallShield => Buff::new("Wall Shield", "WALL", self.color(), 6.)
+                .with(Stat::WallDamageReduction, Modifier::Add(1.)),
+            PowerUp::Multishot => Buff::new("Multishot", "MULTI", self.color(), 8.)
+                .with(Stat::ProjectileCount, Modifier::Add(2.)),
+            PowerUp::Haste => Buff::new("Haste", "SPD", self.color(), 10.)
+                .with(Stat::MoveSpeed, Modifier::Multiply(1.6)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum Loot {
+    Coins(usize),
+    // refills the wizard's mana so the next cast is ready straight away
+    Mana,
+    PowerUp(PowerUp),
+}
+
+impl Loot {
+    fn color(&self) -> Color {
+        match self {
+            Loot::Coins(_) => Color::rgb(1., 0.85, 0.2),
+            Loot::Mana => Color::rgb(0.3, 0.5, 1.),
+            Loot::PowerUp(power_up) => power_up.color(),
+        }
+    }
+
+    fn size(&self) -> Vec2 {
+        match self {
+            Loot::Coins(_) | Loot::Mana => Vec2::splat(14.),
+            Loot::PowerUp(_) => Vec2::splat(24.),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LootEntry {
+    pub weight: u32,
+    // `None` rolls nothing
+    pub drop: Option<Loot>,
+}
+
+// Rolled `rolls` times when an enemy dies, each roll picking one entry by weight
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct LootTable {
+    pub rolls: u32,
+    pub entries: Vec<LootEntry>,
+}
+
+impl LootTable {
+    fn roll(&self, rng: &mut impl Rng) -> Vec<Loot> {
+        let total: u32 = self.entries.iter().map(|entry| entry.weight).sum();
+        if total == 0 {
+            return Vec::new();
+        }
+
+        let mut drops = Vec::new();
+        for _ in 0..self.rolls {
+            let mut roll = rng.gen_range(0..total);
+
+            for entry in self.entries.iter() {
+                if roll < entry.weight {
+                    drops.extend(entry.drop);
+                    break;
+                }
+                roll -= entry.weight;
+            }
+        }
+
+        drops
+    }
+}
+
+#[derive(Component)]
+pub struct Pickup {
+    loot: Loot,
+    velocity: Vec2,
+    age: f32,
+}
+
+impl Pickup {
+    const LIFETIME: f32 = 12.;
+    const SETTLE_TIME: f32 = 0.5;
+    // pulled in while the wizard stands within this many units of its lane
+    const MAGNET_REACH: f32 = 120.;
+    const MAGNET_SPEED: f32 = 900.;
+
+    pub fn loot(&self) -> Loot {
+        self.loot
+    }
+
+    pub fn size(&self) -> Vec2 {
+        self.loot.size()
+    }
+}
+
+// The wizard touched a pickup
+pub struct LootCollected(pub Loot);
+
+fn reset_loot(mut commands: Commands, query: Query<Entity, With<Pickup>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+fn drop_loot(
+    mut commands: Commands,
+    mut events: EventReader<EnemyKilled>,
+    archetypes: Res<Archetypes>,
+    mut game_rng: ResMut<GameRng>,
+) {
+    for event in events.iter() {
+        let rng = game_rng.rng();
+
+        for loot in archetypes.get(event.kind).loot.roll(rng) {
+            // scatter in every direction from where the enemy fell
+            let angle = rng.gen_range(0. ..TAU);
+            let speed = rng.gen_range(150. ..350.);
+
+            commands
+                .spawn(SpriteBundle {
+                    sprite: Sprite {
+                        color: loot.color(),
+                        custom_size: Some(loot.size()),
+                        ..Default::default()
+                    },
+                    transform: Transform::from_translation(event.position.extend(50.)),
+                    ..Default::default()
+                })
+                .insert(Pickup {
+                    loot,
+                    velocity: Vec2::from_angle(angle) * speed,
+                    age: 0.,
+                });
+        }
+    }
+}
+
+fn move_pickups(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Pickup, &mut Transform, &mut Sprite), Without<Player>>,
+    player_query: Query<&Transform, With<Player>>,
+    time: Res<Time>,
+) {
+    let player = player_query.single().translation.truncate();
+    let delta = time.delta_seconds();
+
+    for (entity, mut pickup, mut transform, mut sprite) in query.iter_mut() {
+        pickup.age += delta;
+        if pickup.age >= Pickup::LIFETIME {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        let position = transform.translation.truncate();
+        if pickup.age >= Pickup::SETTLE_TIME && (player.y - position.y).abs() <= Pickup::MAGNET_REACH {
+            let pull = (player - position).normalize_or_zero() * Pickup::MAGNET_SPEED;
+            pickup.velocity = pickup.velocity.lerp(pull, (5. * delta).min(1.));
+        } else {
+            pickup.velocity *= (1. - 4. * delta).max(0.);
+        }
+
+        transform.translation.x += pickup.velocity.x * delta;
+        transform.translation.y += pickup.velocity.y * delta;
+
+        // blink for the last few seconds before it's gone
+        let left = Pickup::LIFETIME - pickup.age;
+        sprite.color.set_a(if left < 3. && (left * 10.).sin() < 0. { 0.3 } else { 1. });
+    }
+}
+
+fn collect_loot(
+    mut events: EventReader<LootCollected>,
+    mut player_query: Query<(&mut Caster, &mut Buffs), With<Player>>,
+    mut wallet: ResMut<Wallet>,
+) {
+    let (mut caster, mut buffs) = player_query.single_mut();
+
+    for event in events.iter() {
+        match event.0 {
+            Loot::Coins(amount) => wallet.earn(Income::Loot, amount),
+            Loot::Mana => caster.restore_mana(),
+            Loot::PowerUp(power_up) => buffs.add(power_up.buff()),
+        }
+    }
+}
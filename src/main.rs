@@ -6,13 +6,52 @@ mod enemy;
 mod collisions;
 mod wall;
 mod ui;
+mod sound;
+mod save;
+mod profile;
+mod menu;
+mod stats;
+mod feedback;
+mod health_bars;
+mod particles;
+mod pool;
+mod boss;
+mod ai;
+mod damage;
+mod loot;
+mod buffs;
+mod patterns;
+mod traps;
+mod towers;
+mod economy;
+mod console;
+mod debug;
 
-use commons::setup;
+use commons::{setup, AppState};
 use player::PlayerPlugin;
 use enemy::EnemyPlugin;
 use collisions::CollisionPlugin;
 use wall::WallPlugin;
 use ui::UiPlugin;
+use sound::SoundPlugin;
+use save::SavePlugin;
+use profile::ProfilePlugin;
+use menu::MenuPlugin;
+use stats::StatsPlugin;
+use feedback::FeedbackPlugin;
+use health_bars::HealthBarPlugin;
+use particles::ParticlePlugin;
+use boss::BossPlugin;
+use ai::AiPlugin;
+use damage::DamagePlugin;
+use loot::LootPlugin;
+use buffs::BuffPlugin;
+use patterns::PatternPlugin;
+use traps::TrapPlugin;
+use towers::TowerPlugin;
+use economy::EconomyPlugin;
+use console::ConsolePlugin;
+use debug::DebugPlugin;
 
 fn main() {
     App::new()
@@ -27,11 +66,31 @@ fn main() {
             },
             ..Default::default() })
             .set(ImagePlugin::default_nearest()))
+        .add_state(AppState::MainMenu)
         .add_plugin(PlayerPlugin)
         .add_plugin(EnemyPlugin)
         .add_plugin(CollisionPlugin)
         .add_plugin(WallPlugin)
         .add_plugin(UiPlugin)
+        .add_plugin(SoundPlugin)
+        .add_plugin(SavePlugin)
+        .add_plugin(ProfilePlugin)
+        .add_plugin(MenuPlugin)
+        .add_plugin(StatsPlugin)
+        .add_plugin(FeedbackPlugin)
+        .add_plugin(HealthBarPlugin)
+        .add_plugin(ParticlePlugin)
+        .add_plugin(BossPlugin)
+        .add_plugin(AiPlugin)
+        .add_plugin(DamagePlugin)
+        .add_plugin(LootPlugin)
+        .add_plugin(BuffPlugin)
+        .add_plugin(PatternPlugin)
+        .add_plugin(TrapPlugin)
+        .add_plugin(TowerPlugin)
+        .add_plugin(EconomyPlugin)
+        .add_plugin(ConsolePlugin)
+        .add_plugin(DebugPlugin)
         .add_startup_system_to_stage(StartupStage::PreStartup, setup)
         .run();
 }
\ No newline at end of file
@@ -0,0 +1,347 @@
+use bevy::prelude::*;
+
+use crate::commons::AppState;
+use crate::economy::{Expense, Income, Wallet};
+use crate::profile::{AwardEssence, EssenceAwarded, Profile, Skill};
+use crate::stats::{LastScore, Leaderboard, RecordScore, RunStats};
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(AppState::MainMenu).with_system(spawn_main_menu))
+            .add_system_set(SystemSet::on_update(AppState::MainMenu).with_system(click_menu_button))
+            .add_system_set(SystemSet::on_exit(AppState::MainMenu).with_system(despawn_menu_screen))
+            .add_system_set(
+                SystemSet::on_enter(AppState::GameOver).with_system(
+                    spawn_game_over_screen
+                        .after(AwardEssence)
+                        .after(RecordScore),
+                ),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::GameOver)
+                    .with_system(click_menu_button)
+                    .with_system(update_skill_buttons),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::GameOver).with_system(despawn_menu_screen));
+    }
+}
+
+#[derive(Component)]
+struct MenuScreen;
+
+#[derive(Component)]
+enum MenuButton {
+    Unlock(Skill),
+    NewRun,
+    MainMenu,
+}
+
+#[derive(Component)]
+struct EssenceText;
+
+fn skill_label(profile: &Profile, skill: Skill) -> String {
+    let level = profile.level(skill);
+
+    if level >= skill.max_level() {
+        format!("{} {}/{} MAX", skill.name(), level, skill.max_level())
+    } else if !profile.meets_requirements(skill) {
+        let requires = skill
+            .requires()
+            .iter()
+            .map(|(required, level)| format!("{} {}", required.name(), level))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("{} - needs {}", skill.name(), requires)
+    } else {
+        format!(
+            "{} {}/{} - {} essence",
+            skill.name(),
+            level,
+            skill.max_level(),
+            skill.cost(level)
+        )
+    }
+}
+
+fn screen_root() -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+            justify_content: JustifyContent::SpaceEvenly,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        background_color: Color::rgba(0., 0., 0., 0.85).into(),
+        ..default()
+    }
+}
+
+fn column() -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        background_color: Color::NONE.into(),
+        ..default()
+    }
+}
+
+fn spawn_button(parent: &mut ChildBuilder, text: String, button: MenuButton, style: &TextStyle) {
+    parent
+        .spawn(ButtonBundle {
+            style: Style {
+                size: Size::new(Val::Px(380.), Val::Px(30.)),
+                margin: UiRect::all(Val::Px(4.)),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(text, style.clone()));
+        })
+        .insert(button);
+}
+
+fn spawn_leaderboard(parent: &mut ChildBuilder, leaderboard: &Leaderboard, font: Handle<Font>) {
+    let heading_style = TextStyle {
+        font: font.clone(),
+        font_size: 30.,
+        color: Color::WHITE,
+    };
+    let row_style = TextStyle {
+        font,
+        font_size: 20.,
+        color: Color::WHITE,
+    };
+
+    parent.spawn(column()).with_children(|parent| {
+        parent.spawn(TextBundle::from_section("High Scores", heading_style));
+
+        if leaderboard.entries().is_empty() {
+            parent.spawn(TextBundle::from_section("No runs yet", row_style.clone()));
+        }
+
+        for (i, entry) in leaderboard.entries().iter().enumerate() {
+            parent.spawn(TextBundle::from_section(
+                format!(
+                    "{}. {} - wave {}, {} kills, {:.0}s",
+                    i + 1,
+                    entry.score,
+                    entry.waves,
+                    entry.kills,
+                    entry.time_survived
+                ),
+                row_style.clone(),
+            ));
+        }
+    });
+}
+
+fn spawn_main_menu(mut commands: Commands, asset_server: Res<AssetServer>, leaderboard: Res<Leaderboard>) {
+    let font: Handle<Font> = asset_server.load("font.ttf");
+    let title_style = TextStyle {
+        font: font.clone(),
+        font_size: 50.,
+        color: Color::WHITE,
+    };
+    let button_style = TextStyle {
+        font: font.clone(),
+        font_size: 20.,
+        color: Color::BLACK,
+    };
+
+    commands
+        .spawn(screen_root())
+        .insert(MenuScreen)
+        .with_children(|parent| {
+            parent.spawn(column()).with_children(|parent| {
+                parent.spawn(TextBundle::from_section("Wizard Defense", title_style));
+                spawn_button(parent, "Start Run".to_string(), MenuButton::NewRun, &button_style);
+            });
+
+            spawn_leaderboard(parent, &leaderboard, font);
+        });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_game_over_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    profile: Res<Profile>,
+    awarded: Res<EssenceAwarded>,
+    stats: Res<RunStats>,
+    wallet: Res<Wallet>,
+    last_score: Res<LastScore>,
+    leaderboard: Res<Leaderboard>,
+) {
+    let font: Handle<Font> = asset_server.load("font.ttf");
+    let text_style = TextStyle {
+        font: font.clone(),
+        font_size: 40.,
+        color: Color::WHITE,
+    };
+    let small_style = TextStyle {
+        font: font.clone(),
+        font_size: 20.,
+        color: Color::WHITE,
+    };
+    let button_style = TextStyle {
+        font: font.clone(),
+        font_size: 20.,
+        color: Color::BLACK,
+    };
+
+    let rank = match last_score.rank {
+        Some(rank) => format!(" (#{} all time)", rank),
+        None => String::new(),
+    };
+
+    commands
+        .spawn(screen_root())
+        .insert(MenuScreen)
+        .with_children(|parent| {
+            parent.spawn(column()).with_children(|parent| {
+                let title = if stats.wizard_fallen {
+                    "The Wizard Has Fallen"
+                } else {
+                    "The Wall Has Fallen"
+                };
+                parent.spawn(TextBundle::from_section(title, text_style.clone()));
+
+                parent.spawn(TextBundle::from_section(
+                    format!("Score: {}{}", last_score.score, rank),
+                    text_style.clone(),
+                ));
+
+                parent.spawn(TextBundle::from_section(
+                    format!(
+                        "{} kills, {:.0}% accuracy, {:.0}s survived\n{:.0} wall damage taken, ${} earned, ${} spent",
+                        stats.total_kills(),
+                        stats.accuracy() * 100.,
+                        stats.time_survived,
+                        stats.wall_damage_taken,
+                        wallet.total_earned(),
+                        wallet.total_spent()
+                    ),
+                    small_style.clone(),
+                ));
+
+                let kills = stats
+                    .kills
+                    .iter()
+                    .map(|(kind, count)| format!("{} {}", count, kind.name()))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let damage = stats
+                    .damage_by_spell
+                    .iter()
+                    .map(|(spell, damage)| format!("{:.0} {}", damage, spell.name()))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                parent.spawn(TextBundle::from_section(
+                    format!("Kills: {}\nDamage: {}", kills, damage),
+                    small_style.clone(),
+                ));
+
+                // the ledger, leaving out anything that never came up
+                let earned = Income::ALL
+                    .iter()
+                    .map(|income| (income.name(), wallet.earned_from(*income)))
+                    .filter(|(_, amount)| *amount > 0)
+                    .map(|(name, amount)| format!("${} {}", amount, name))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let spent = Expense::ALL
+                    .iter()
+                    .map(|expense| (expense.name(), wallet.spent_on(*expense)))
+                    .filter(|(_, amount)| *amount > 0)
+                    .map(|(name, amount)| format!("${} {}", amount, name))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                parent.spawn(TextBundle::from_section(
+                    format!("Earned: {}\nSpent: {}", earned, spent),
+                    small_style.clone(),
+                ));
+
+                parent
+                    .spawn(TextBundle::from_sections([
+                        TextSection::new(
+                            format!("+{} arcane essence\n", awarded.0),
+                            small_style.clone(),
+                        ),
+                        TextSection::new(format!("{} essence", profile.essence()), small_style),
+                    ]))
+                    .insert(EssenceText);
+
+                for skill in Skill::ALL {
+                    spawn_button(parent, skill_label(&profile, skill), MenuButton::Unlock(skill), &button_style);
+                }
+                spawn_button(parent, "New Run".to_string(), MenuButton::NewRun, &button_style);
+                spawn_button(parent, "Main Menu".to_string(), MenuButton::MainMenu, &button_style);
+            });
+
+            spawn_leaderboard(parent, &leaderboard, font);
+        });
+}
+
+fn despawn_menu_screen(mut commands: Commands, query: Query<Entity, With<MenuScreen>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn click_menu_button(
+    query: Query<(&Interaction, &MenuButton), (Changed<Interaction>, With<Button>)>,
+    mut profile: ResMut<Profile>,
+    mut state: ResMut<State<AppState>>,
+) {
+    for (interaction, button) in query.iter() {
+        if *interaction == Interaction::Clicked {
+            match button {
+                MenuButton::Unlock(skill) => {
+                    if profile.try_unlock(*skill) {
+                        profile.save();
+                    }
+                }
+                MenuButton::NewRun => {
+                    let _ = state.set(AppState::Playing);
+                }
+                MenuButton::MainMenu => {
+                    let _ = state.set(AppState::MainMenu);
+                }
+            };
+        }
+    }
+}
+
+// Refresh labels after a purchase so levels and costs stay current
+fn update_skill_buttons(
+    profile: Res<Profile>,
+    button_query: Query<(&MenuButton, &Children)>,
+    mut text_query: Query<&mut Text, Without<EssenceText>>,
+    mut essence_query: Query<&mut Text, With<EssenceText>>,
+) {
+    if !profile.is_changed() {
+        return;
+    }
+
+    for (button, children) in button_query.iter() {
+        if let MenuButton::Unlock(skill) = button {
+            for child in children.iter() {
+                if let Ok(mut text) = text_query.get_mut(*child) {
+                    text.sections[0].value = skill_label(&profile, *skill);
+                }
+            }
+        }
+    }
+
+    for mut text in essence_query.iter_mut() {
+        text.sections[1].value = format!("{} essence", profile.essence());
+    }
+}
@@ -0,0 +1,324 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::commons::{load_data, AppState};
+use crate::debug::timed;
+use crate::enemy::EnemyKilled;
+use crate::wall::WallDamaged;
+
+pub struct ParticlePlugin;
+
+impl Plugin for ParticlePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ParticlePresets::load())
+            .init_resource::<ParticlePool>()
+            .add_event::<ParticleBurst>()
+            .add_system_set(SystemSet::on_update(AppState::Playing).with_system(timed(run_emitters)))
+            .add_system(burst_on_death)
+            .add_system(burst_on_wall_damage)
+            .add_system(spawn_bursts.after(run_emitters))
+            .add_system(timed(update_particles));
+    }
+}
+
+pub const PARTICLES_FILE: &str = "particles.ron";
+const BUILT_IN_PARTICLES: &str = include_str!("../assets/particles.ron");
+
+#[derive(Clone, Copy)]
+pub enum ParticlePreset {
+    FireballTrail,
+    ImpactSparks,
+    DeathDust,
+    WallDebris,
+    FrostMist,
+}
+
+// How one kind of particle is emitted and how it looks over its life
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EmitterConfig {
+    // particles per second for emitters that follow an entity
+    pub rate: f32,
+    // particles per one-shot burst
+    pub burst: usize,
+    pub lifetime: f32,
+    pub speed: (f32, f32),
+    // direction of travel and the spread either side of it, in degrees
+    pub angle: f32,
+    pub spread: f32,
+    pub gravity: f32,
+    pub size: (f32, f32),
+    pub start_color: (f32, f32, f32, f32),
+    pub end_color: (f32, f32, f32, f32),
+}
+
+impl EmitterConfig {
+    // Hand edited presets can have their bounds the wrong way round, which
+    // would panic the first time a range is rolled
+    fn sanitized(mut self) -> Self {
+        if self.speed.0 > self.speed.1 {
+            self.speed = (self.speed.1, self.speed.0);
+        }
+        self.spread = self.spread.abs();
+        self.lifetime = self.lifetime.max(0.01);
+        self.rate = self.rate.max(0.);
+        self
+    }
+}
+
+#[derive(Resource, Serialize, Deserialize)]
+pub struct ParticlePresets {
+    pub fireball_trail: EmitterConfig,
+    pub impact_sparks: EmitterConfig,
+    pub death_dust: EmitterConfig,
+    pub wall_debris: EmitterConfig,
+    pub frost_mist: EmitterConfig,
+}
+
+impl ParticlePresets {
+    pub fn load() -> Self {
+        load_data::<Self>(PARTICLES_FILE, BUILT_IN_PARTICLES).sanitized()
+    }
+
+    fn sanitized(self) -> Self {
+        Self {
+            fireball_trail: self.fireball_trail.sanitized(),
+            impact_sparks: self.impact_sparks.sanitized(),
+            death_dust: self.death_dust.sanitized(),
+            wall_debris: self.wall_debris.sanitized(),
+            frost_mist: self.frost_mist.sanitized(),
+        }
+    }
+
+    pub fn get(&self, preset: ParticlePreset) -> &EmitterConfig {
+        match preset {
+            ParticlePreset::FireballTrail => &self.fireball_trail,
+            ParticlePreset::ImpactSparks => &self.impact_sparks,
+            ParticlePreset::DeathDust => &self.death_dust,
+            ParticlePreset::WallDebris => &self.wall_debris,
+            ParticlePreset::FrostMist => &self.frost_mist,
+        }
+    }
+}
+
+// Continuously emits particles from wherever its entity is
+#[derive(Component)]
+pub struct ParticleEmitter {
+    preset: ParticlePreset,
+    accumulator: f32,
+}
+
+impl ParticleEmitter {
+    pub fn new(preset: ParticlePreset) -> Self {
+        Self {
+            preset,
+            accumulator: 0.,
+        }
+    }
+}
+
+pub struct ParticleBurst {
+    pub preset: ParticlePreset,
+    pub position: Vec2,
+    pub count: Option<usize>,
+}
+
+impl ParticleBurst {
+    pub fn new(preset: ParticlePreset, position: Vec2) -> Self {
+        Self {
+            preset,
+            position,
+            count: None,
+        }
+    }
+}
+
+#[derive(Component)]
+struct Particle {
+    preset: ParticlePreset,
+    velocity: Vec2,
+    age: f32,
+    lifetime: f32,
+    active: bool,
+}
+
+// Dead particles are hidden and parked here instead of being despawned
+#[derive(Resource, Default)]
+struct ParticlePool {
+    free: Vec<Entity>,
+    live: usize,
+}
+
+impl ParticlePool {
+    const MAX_LIVE: usize = 2000;
+}
+
+fn lerp_color(from: (f32, f32, f32, f32), to: (f32, f32, f32, f32), t: f32) -> Color {
+    Color::rgba(
+        from.0 + (to.0 - from.0) * t,
+        from.1 + (to.1 - from.1) * t,
+        from.2 + (to.2 - from.2) * t,
+        from.3 + (to.3 - from.3) * t,
+    )
+}
+
+fn emit(
+    commands: &mut Commands,
+    pool: &mut ParticlePool,
+    particle_query: &mut Query<(&mut Particle, &mut Transform, &mut Sprite, &mut Visibility)>,
+    config: &EmitterConfig,
+    preset: ParticlePreset,
+    position: Vec2,
+) {
+    if pool.live >= ParticlePool::MAX_LIVE {
+        return;
+    }
+
+    let mut rng = rand::thread_rng();
+    let angle = (config.angle + rng.gen_range(-config.spread..=config.spread)).to_radians();
+    let speed = rng.gen_range(config.speed.0..=config.speed.1);
+
+    let particle = Particle {
+        preset,
+        velocity: Vec2::new(angle.cos(), angle.sin()) * speed,
+        age: 0.,
+        lifetime: config.lifetime,
+        active: true,
+    };
+    let translation = position.extend(120.);
+    let color = lerp_color(config.start_color, config.end_color, 0.);
+    let size = Vec2::splat(config.size.0);
+
+    pool.live += 1;
+
+    // reuse a parked particle where we can
+    while let Some(entity) = pool.free.pop() {
+        if let Ok((mut old, mut transform, mut sprite, mut visibility)) = particle_query.get_mut(entity) {
+            *old = particle;
+            transform.translation = translation;
+            sprite.color = color;
+            sprite.custom_size = Some(size);
+            visibility.is_visible = true;
+            return;
+        }
+    }
+
+    commands
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                color,
+                custom_size: Some(size),
+                ..Default::default()
+            },
+            transform: Transform::from_translation(translation),
+            ..Default::default()
+        })
+        .insert(particle);
+}
+
+fn run_emitters(
+    mut emitter_query: Query<(&mut ParticleEmitter, &Transform)>,
+    mut bursts: EventWriter<ParticleBurst>,
+    presets: Res<ParticlePresets>,
+    time: Res<Time>,
+) {
+    for (mut emitter, transform) in emitter_query.iter_mut() {
+        let config = presets.get(emitter.preset);
+        emitter.accumulator += config.rate * time.delta_seconds();
+
+        let count = emitter.accumulator.floor();
+        if count >= 1. {
+            emitter.accumulator -= count;
+            bursts.send(ParticleBurst {
+                preset: emitter.preset,
+                position: transform.translation.truncate(),
+                count: Some(count as usize),
+            });
+        }
+    }
+}
+
+fn burst_on_death(mut events: EventReader<EnemyKilled>, mut bursts: EventWriter<ParticleBurst>) {
+    for event in events.iter() {
+        bursts.send(ParticleBurst::new(ParticlePreset::DeathDust, event.position));
+    }
+}
+
+fn burst_on_wall_damage(mut events: EventReader<WallDamaged>, mut bursts: EventWriter<ParticleBurst>) {
+    for event in events.iter() {
+        bursts.send(ParticleBurst::new(ParticlePreset::WallDebris, event.position));
+    }
+}
+
+fn spawn_bursts(
+    mut commands: Commands,
+    mut events: EventReader<ParticleBurst>,
+    mut pool: ResMut<ParticlePool>,
+    mut particle_query: Query<(&mut Particle, &mut Transform, &mut Sprite, &mut Visibility)>,
+    presets: Res<ParticlePresets>,
+) {
+    for event in events.iter() {
+        let config = presets.get(event.preset);
+
+        for _ in 0..event.count.unwrap_or(config.burst) {
+            emit(&mut commands, &mut pool, &mut particle_query, config, event.preset, event.position);
+        }
+    }
+}
+
+fn update_particles(
+    mut pool: ResMut<ParticlePool>,
+    mut particle_query: Query<(Entity, &mut Particle, &mut Transform, &mut Sprite, &mut Visibility)>,
+    presets: Res<ParticlePresets>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_seconds();
+
+    for (entity, mut particle, mut transform, mut sprite, mut visibility) in particle_query.iter_mut() {
+        if !particle.active {
+            continue;
+        }
+
+        particle.age += dt;
+        if particle.age >= particle.lifetime {
+            particle.active = false;
+            visibility.is_visible = false;
+            pool.live -= 1;
+            pool.free.push(entity);
+            continue;
+        }
+
+        let config = presets.get(particle.preset);
+        let t = particle.age / particle.lifetime;
+
+        particle.velocity.y += config.gravity * dt;
+        transform.translation += (particle.velocity * dt).extend(0.);
+        sprite.color = lerp_color(config.start_color, config.end_color, t);
+        sprite.custom_size = Some(Vec2::splat(config.size.0 + (config.size.1 - config.size.0) * t));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commons::built_in_data;
+
+    #[test]
+    fn built_in_presets_parse() {
+        let presets: ParticlePresets = built_in_data(PARTICLES_FILE, BUILT_IN_PARTICLES);
+        assert_eq!(presets.get(ParticlePreset::ImpactSparks).burst, 12);
+    }
+
+    #[test]
+    fn reversed_bounds_are_swapped() {
+        let mut presets: ParticlePresets = built_in_data(PARTICLES_FILE, BUILT_IN_PARTICLES);
+        presets.death_dust.speed = (70., 20.);
+        presets.death_dust.spread = -80.;
+        presets.death_dust.lifetime = -1.;
+
+        let dust = presets.sanitized().death_dust;
+        assert_eq!(dust.speed, (20., 70.));
+        assert_eq!(dust.spread, 80.);
+        assert!(dust.lifetime > 0.);
+    }
+}
@@ -0,0 +1,158 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::TAU;
+
+use crate::commons::{built_in_data, load_data, AppState};
+use crate::player::Upgrades;
+
+pub struct PatternPlugin;
+
+impl Plugin for PatternPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PatternBook::load())
+            .init_resource::<SelectedPattern>()
+            .add_system_set(SystemSet::on_enter(AppState::Playing).with_system(reset_pattern))
+            .add_system_set(SystemSet::on_update(AppState::Playing).with_system(cycle_pattern));
+    }
+}
+
+pub const PATTERNS_FILE: &str = "patterns.ron";
+const BUILT_IN_PATTERNS: &str = include_str!("../assets/patterns.ron");
+
+// How a pattern lays out its projectiles. Angles are in degrees.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub enum Shape {
+    // one projectile straight at the target
+    Single,
+    // `count` projectiles fanned evenly across `angle`
+    Spread { count: usize, angle: f32 },
+    // `count` parallel projectiles side by side, `spacing` apart
+    Volley { count: usize, spacing: f32 },
+    // `count` projectiles evenly around a full circle
+    Nova { count: usize },
+}
+
+// One projectile of a cast, relative to the aim
+pub struct Shot {
+    // radians to turn away from the aim
+    pub rotation: f32,
+    // how far to shift sideways, perpendicular to the aim
+    pub offset: f32,
+}
+
+fn one() -> f32 {
+    1.
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Pattern {
+    pub name: String,
+    // price in the shop, unlocked in book order
+    pub cost: usize,
+    pub shape: Shape,
+    // scales each projectile's sprite and hitbox
+    #[serde(default = "one")]
+    pub size: f32,
+    // scales each projectile's damage, so wide patterns don't multiply damage outright
+    #[serde(default = "one")]
+    pub damage: f32,
+}
+
+impl Pattern {
+    // degrees added to a single shot's fan for each extra projectile
+    const EXTRA_SPREAD: f32 = 8.;
+
+    // Extra projectiles from buffs widen the pattern rather than replace it
+    pub fn shots(&self, extra: usize) -> Vec<Shot> {
+        match self.shape {
+            Shape::Single => Self::spread(1 + extra, Self::EXTRA_SPREAD * extra as f32),
+            Shape::Spread { count, angle } => Self::spread(count + extra, angle),
+            Shape::Volley { count, spacing } => {
+                let count = count + extra;
+                (0..count)
+                    .map(|i| Shot {
+                        rotation: 0.,
+                        offset: (i as f32 - (count - 1) as f32 / 2.) * spacing,
+                    })
+                    .collect()
+            }
+            Shape::Nova { count } => {
+                let count = count + extra;
+                (0..count)
+                    .map(|i| Shot {
+                        rotation: i as f32 * TAU / count as f32,
+                        offset: 0.,
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    fn spread(count: usize, angle: f32) -> Vec<Shot> {
+        if count <= 1 {
+            return vec![Shot {
+                rotation: 0.,
+                offset: 0.,
+            }];
+        }
+
+        let angle = angle.to_radians();
+        (0..count)
+            .map(|i| Shot {
+                rotation: -angle / 2. + angle * i as f32 / (count - 1) as f32,
+                offset: 0.,
+            })
+            .collect()
+    }
+}
+
+// Every pattern the wizard can learn, the first one known from the start
+#[derive(Resource, Serialize, Deserialize)]
+pub struct PatternBook {
+    patterns: Vec<Pattern>,
+}
+
+impl PatternBook {
+    pub fn load() -> Self {
+        let book: Self = load_data(PATTERNS_FILE, BUILT_IN_PATTERNS);
+
+        if book.patterns.is_empty() {
+            error!("{} has no patterns, using the built in copy", PATTERNS_FILE);
+            return built_in_data(PATTERNS_FILE, BUILT_IN_PATTERNS);
+        }
+
+        book
+    }
+
+    pub fn get(&self, index: usize) -> &Pattern {
+        &self.patterns[index.min(self.patterns.len() - 1)]
+    }
+
+    // The next pattern the shop sells, if any are left
+    pub fn next_unlock(&self, upgrades: &Upgrades) -> Option<&Pattern> {
+        self.patterns.get(upgrades.patterns as usize + 1)
+    }
+
+    fn known(&self, upgrades: &Upgrades) -> usize {
+        (upgrades.patterns as usize + 1).min(self.patterns.len())
+    }
+}
+
+// Index into the pattern book of the pattern being cast
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct SelectedPattern(pub usize);
+
+fn reset_pattern(mut selected: ResMut<SelectedPattern>) {
+    *selected = SelectedPattern::default();
+}
+
+fn cycle_pattern(
+    keyboard: Res<Input<KeyCode>>,
+    book: Res<PatternBook>,
+    upgrades: Res<Upgrades>,
+    mut selected: ResMut<SelectedPattern>,
+) {
+    if keyboard.just_pressed(KeyCode::P) {
+        **selected = (**selected + 1) % book.known(&upgrades);
+    }
+}
@@ -1,38 +1,137 @@
-use bevy::prelude::*;
-use std::default::Default;
+use bevy::{prelude::*, sprite::Anchor};
+use serde::{Deserialize, Serialize};
+use std::{default::Default, f32::consts::PI};
 
-use crate::commons::GameTextures;
-use crate::enemy::{Enemy, EnemyState};
+use crate::buffs::{Buffs, PlayerStats, ResolveStats};
+use crate::commons::{cursor_world_position, AppState, GameRng, GameTextures, ARENA_HALF_SIZE};
+use crate::console::Cheats;
+use crate::damage::{Damage, DamageKind, DamageStyle, Hit, ResolveHits};
+use crate::debug::timed;
+use crate::enemy::{Elevation, Enemy, EnemyState};
+use crate::feedback::ScreenShake;
+use crate::patterns::{PatternBook, SelectedPattern};
+use crate::particles::{ParticleBurst, ParticleEmitter, ParticlePreset};
+use crate::pool::EntityPool;
+use crate::profile::{Profile, Skill};
+use crate::sound::{Sound, SoundEvent};
+use crate::stats::RunStats;
+use crate::wall::{Wall, WallRepair};
 
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(spawn_player)
-            .add_system(movement_player)
-            .add_system(shoot)
-            .add_system(movement_projectile)
-            .add_system(add_wealth_system);
+        app.init_resource::<Upgrades>()
+            .init_resource::<SelectedSpell>()
+            .init_resource::<AimMode>()
+            .init_resource::<CastMode>()
+            .init_resource::<EntityPool<Projectile>>()
+            .add_event::<CastSpell>()
+            .add_event::<PlayerHurt>()
+            .add_startup_system(spawn_player)
+            .add_system_set(SystemSet::on_enter(AppState::Playing).with_system(reset_player))
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(movement_player.after(ResolveStats))
+                    .with_system(select_spell)
+                    .with_system(toggle_aim_mode)
+                    .with_system(toggle_cast_mode)
+                    .with_system(trigger_casts.after(ResolveStats))
+                    .with_system(timed(shoot).after(trigger_casts))
+                    .with_system(update_charge_indicator.after(trigger_casts))
+                    .with_system(timed(move_projectiles).before(ResolveHits))
+                    .with_system(hurt_player)
+                    .with_system(recover_player.after(hurt_player))
+                    .with_system(wizard_fallen.after(hurt_player)),
+            );
     }
 }
 
-#[derive(Component)]
+#[derive(Component, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Player {
     speed: f32,
-    wealth: usize,
+    health: f32,
+    max_health: f32,
+    // seconds left before the wizard can be hurt again
+    #[serde(skip)]
+    invulnerable: f32,
+    #[serde(skip)]
+    knockback: Vec2,
 }
 
 impl Player {
-    pub fn wealth(&self) -> usize {
-        self.wealth
+    const HOME_X: f32 = 750.;
+    const SIZE: Vec2 = Vec2::new(80., 80.);
+    const INVULNERABLE_TIME: f32 = 1.;
+    const KNOCKBACK: f32 = 500.;
+    pub const POTION_HEAL: f32 = 40.;
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    pub fn health(&self) -> f32 {
+        self.health
+    }
+
+    pub fn max_health(&self) -> f32 {
+        self.max_health
     }
 
-    pub fn add_wealth(&mut self, amount: usize) {
-        self.wealth += amount;
+    pub fn heal(&mut self, amount: f32) {
+        self.health = (self.health + amount).min(self.max_health);
     }
 
-    pub fn spend(&mut self, amount: usize) {
-        self.wealth -= amount;
+    pub fn size(&self) -> Vec2 {
+        Self::SIZE
+    }
+}
+
+impl Default for Player {
+    fn default() -> Self {
+        Self {
+            speed: 300.,
+            health: 100.,
+            max_health: 100.,
+            invulnerable: 0.,
+            knockback: Vec2::ZERO,
+        }
+    }
+}
+
+// Something struck the wizard, `from` is where the blow came from
+pub struct PlayerHurt {
+    pub amount: f32,
+    pub from: Vec2,
+}
+
+// The wizard is down and the run ends once the timer runs out
+#[derive(Component)]
+struct Dying(Timer);
+
+// Everything bought in the shop over the course of a run
+#[derive(Resource, Default, Clone, Serialize, Deserialize)]
+pub struct Upgrades {
+    pub wall_max_health: u32,
+    #[serde(default)]
+    pub crit: u32,
+    // patterns learned beyond the first one in the book
+    #[serde(default)]
+    pub patterns: u32,
+}
+
+impl Upgrades {
+    pub fn crit_chance(&self) -> f32 {
+        0.05 + 0.05 * self.crit as f32
+    }
+
+    pub fn crit_multiplier(&self) -> f32 {
+        1.5 + 0.25 * self.crit as f32
     }
 }
 
@@ -41,124 +140,902 @@ fn spawn_player(mut commands: Commands, game_textures: Res<GameTextures>) {
         .spawn(SpriteBundle {
             texture: game_textures.player.clone(),
             sprite: Sprite {
-                custom_size: Some(Vec2::new(80., 80.)),
+                custom_size: Some(Player::SIZE),
 
                 ..Default::default()
             },
             transform: Transform {
-                translation: Vec3::new(750., 100., 10.),
+                translation: Vec3::new(Player::HOME_X, 100., 10.),
                 ..Default::default()
             },
             ..Default::default()
         })
-        .insert(Player {
-            speed: 300.,
-            wealth: 0,
+        .insert(Player::default())
+        .insert(Caster::default())
+        .insert(Buffs::default())
+        .with_children(|parent| {
+            parent
+                .spawn(SpriteBundle {
+                    sprite: Sprite {
+                        custom_size: Some(Vec2::new(0., Caster::BAR_SIZE.y)),
+                        anchor: Anchor::CenterLeft,
+                        ..Default::default()
+                    },
+                    transform: Transform::from_xyz(-Caster::BAR_SIZE.x / 2., 55., 1.),
+                    visibility: Visibility { is_visible: false },
+                    ..Default::default()
+                })
+                .insert(ChargeIndicator);
         });
 }
 
+// Start a new run with a fresh wizard, the profile's starting gold goes in the wallet
+fn reset_player(
+    mut commands: Commands,
+    mut pool: ResMut<EntityPool<Projectile>>,
+    mut player_query: Query<(Entity, &mut Player, &mut Caster, &mut Buffs, &mut Transform, &mut Sprite)>,
+    projectile_query: Query<Entity, With<Projectile>>,
+    mut selected_spell: ResMut<SelectedSpell>,
+) {
+    let (entity, mut player, mut caster, mut buffs, mut transform, mut sprite) = player_query.single_mut();
+    *player = Player::default();
+    *caster = Caster::default();
+    buffs.clear();
+    commands.entity(entity).remove::<Dying>();
+    *transform = Transform::from_xyz(Player::HOME_X, 100., transform.translation.z);
+    sprite.color = Color::WHITE;
+
+    for entity in projectile_query.iter() {
+        release_projectile(&mut commands, &mut pool, entity);
+    }
+
+    commands.insert_resource(Upgrades::default());
+    *selected_spell = SelectedSpell::default();
+}
+
 fn movement_player(
-    mut player_query: Query<(&mut Player, &mut Transform)>,
+    mut player_query: Query<&mut Transform, (With<Player>, Without<Dying>)>,
     keyboard: Res<Input<KeyCode>>,
+    player_stats: Res<PlayerStats>,
+    repair: Res<WallRepair>,
     time: Res<Time>,
 ) {
-    let (player, mut transform) = player_query.single_mut();
+    let mut transform = match player_query.get_single_mut() {
+        Ok(transform) => transform,
+        Err(_) => return,
+    };
+
+    // rooted to the spot while repairing
+    if repair.channeling() {
+        return;
+    }
 
     let y = transform.translation.y;
-    let dy = player.speed * time.delta_seconds();
+    let dy = player_stats.move_speed() * time.delta_seconds();
+
+    if keyboard.pressed(KeyCode::Up) && y + dy + 40. < 385. {
+        transform.translation.y += dy;
+    }
+
+    if keyboard.pressed(KeyCode::Down) && y - dy - 40. > -385. {
+        transform.translation.y -= dy;
+    }
+}
+
+// Logic for shooting
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Spell {
+    #[default]
+    Fireball,
+    FrostBolt,
+    ArcaneMissile,
+    Meteor,
+    ChainOrb,
+    Beam,
+}
+
+impl Spell {
+    pub fn name(&self) -> &str {
+        match self {
+            Spell::Fireball => "Fireball",
+            Spell::FrostBolt => "Frost Bolt",
+            Spell::ArcaneMissile => "Arcane Missile",
+            Spell::Meteor => "Meteor",
+            Spell::ChainOrb => "Chain Orb",
+            Spell::Beam => "Beam",
+        }
+    }
+
+    pub fn damage_kind(&self) -> DamageKind {
+        match self {
+            Spell::Fireball | Spell::Meteor => DamageKind::Fire,
+            Spell::FrostBolt => DamageKind::Frost,
+            Spell::ArcaneMissile | Spell::Beam => DamageKind::Arcane,
+            Spell::ChainOrb => DamageKind::Lightning,
+        }
+    }
 
-    if keyboard.pressed(KeyCode::Up) {
-        if y + dy + 40. < 385. {
-            transform.translation.y += dy;
+    // fire can't reach underground, frost can't reach anything in the air,
+    // and a meteor's blast shakes the ground but passes under flyers
+    pub fn can_hit(&self, elevation: Elevation) -> bool {
+        match self {
+            Spell::Fireball | Spell::ArcaneMissile | Spell::ChainOrb | Spell::Beam => {
+                elevation != Elevation::Underground
+            }
+            Spell::FrostBolt | Spell::Meteor => elevation != Elevation::Air,
         }
     }
 
-    if keyboard.pressed(KeyCode::Down) {
-        if y - dy - 40. > -385. {
-            transform.translation.y -= dy;
+    // the skill that teaches it, the fireball is always known
+    pub fn skill(&self) -> Option<Skill> {
+        match self {
+            Spell::Fireball => None,
+            Spell::FrostBolt => Some(Skill::FrostBolt),
+            Spell::ArcaneMissile => Some(Skill::ArcaneMissile),
+            Spell::Meteor => Some(Skill::Meteor),
+            Spell::ChainOrb => Some(Skill::ChainOrb),
+            Spell::Beam => Some(Skill::Beam),
+        }
+    }
+
+    // everything reuses the fireball sprite, tinted
+    fn color(&self) -> Color {
+        match self {
+            Spell::Fireball => Color::WHITE,
+            Spell::FrostBolt => Color::rgb(0.5, 0.8, 1.),
+            Spell::ArcaneMissile => Color::rgb(0.8, 0.5, 1.),
+            Spell::Meteor => Color::rgb(1., 0.4, 0.2),
+            Spell::ChainOrb => Color::rgb(1., 1., 0.5),
+            Spell::Beam => Color::rgba(0.85, 0.6, 1., 0.8),
+        }
+    }
+
+    // seconds before the wizard can cast again
+    fn cooldown(&self) -> f32 {
+        match self {
+            Spell::Fireball => 0.25,
+            Spell::FrostBolt => 0.3,
+            Spell::ArcaneMissile => 0.2,
+            Spell::Meteor => 1.,
+            Spell::ChainOrb => 0.5,
+            Spell::Beam => 0.8,
+        }
+    }
+
+    fn size(&self) -> Vec2 {
+        match self {
+            Spell::Meteor => Vec2::new(80., 80.),
+            Spell::ChainOrb => Vec2::new(50., 50.),
+            Spell::Beam => Vec2::new(Projectile::BEAM_LENGTH, 16.),
+            _ => Vec2::new(100., 50.),
+        }
+    }
+
+    pub fn projectile(&self) -> Projectile {
+        let (speed, damage, motion) = match self {
+            Spell::Fireball => (500., 20., Motion::Straight),
+            Spell::FrostBolt => (450., 12., Motion::Straight),
+            Spell::ArcaneMissile => (380., 15., Motion::Homing { turn_rate: 4. }),
+            Spell::Meteor => (
+                0.,
+                40.,
+                Motion::Arcing {
+                    from: Vec2::ZERO,
+                    target: Vec2::ZERO,
+                    elapsed: 0.,
+                    duration: 1.,
+                    height: 200.,
+                    radius: 120.,
+                },
+            ),
+            Spell::ChainOrb => (
+                550.,
+                14.,
+                Motion::Bouncing {
+                    bounces_left: 3,
+                    range: 300.,
+                    last_hit: None,
+                },
+            ),
+            // a beam's damage is per second, dealt in ticks
+            Spell::Beam => (
+                0.,
+                60.,
+                Motion::Beam {
+                    tick: Timer::from_seconds(0.1, TimerMode::Repeating),
+                    lifetime: Timer::from_seconds(0.6, TimerMode::Once),
+                },
+            ),
+        };
+
+        Projectile {
+            velocity: Vec2::new(-speed, 0.),
+            damage,
+            spell: *self,
+            motion,
+            scale: 1.,
         }
     }
 }
 
-// Logic for shooting
-// Plan to have several spells, but that is a task for later
+// How a projectile gets from the wizard to whatever it hits
+#[derive(Clone)]
+pub enum Motion {
+    // flies along its velocity until it hits something
+    Straight,
+    // turns toward the nearest enemy it can hurt, at most `turn_rate` radians a second
+    Homing { turn_rate: f32 },
+    // lobbed over everything, bursting over an area when it lands on `target`
+    Arcing {
+        from: Vec2,
+        target: Vec2,
+        elapsed: f32,
+        duration: f32,
+        height: f32,
+        radius: f32,
+    },
+    // ricochets from one enemy to the next closest within `range`
+    Bouncing {
+        bounces_left: u32,
+        range: f32,
+        last_hit: Option<Entity>,
+    },
+    // a line from the caster that hurts everything along it every tick
+    Beam { tick: Timer, lifetime: Timer },
+}
+
 #[derive(Component)]
 pub struct Projectile {
-    speed: f32,
+    velocity: Vec2,
     damage: f32,
+    spell: Spell,
+    motion: Motion,
+    scale: f32,
 }
 
-impl Default for Projectile {
-    fn default() -> Self {
+impl Projectile {
+    pub const BEAM_LENGTH: f32 = 1800.;
+    // how far down the row a lobbed shot lands when not aiming with the mouse
+    const ROW_RANGE: f32 = 600.;
+
+    // A held cast, from 0 for a tap to 1 for a full charge, hits harder
+    // with a bigger, slower projectile
+    pub fn charged(self, charge: f32) -> Self {
         Self {
-            speed: 500.,
-            damage: 20.,
+            velocity: self.velocity * (1. - 0.4 * charge),
+            damage: self.damage * (1. + 2. * charge),
+            scale: 1. + charge,
+            ..self
         }
     }
-}
 
-impl Projectile {
-    pub fn damage(&self) -> f32 {
-        self.damage
+    // Patterns can shrink or grow their projectiles, which also moves the hitbox
+    pub fn scaled(self, factor: f32) -> Self {
+        Self {
+            scale: self.scale * factor,
+            ..self
+        }
+    }
+
+    pub fn empowered(self, multiplier: f32) -> Self {
+        Self {
+            damage: self.damage * multiplier,
+            ..self
+        }
+    }
+
+    // Send it from `origin` toward `target`, keeping its speed
+    pub fn launch(mut self, origin: Vec2, target: Vec2) -> Self {
+        let direction = (target - origin).normalize_or_zero();
+        self.velocity = direction * self.velocity.length();
+
+        match &mut self.motion {
+            Motion::Arcing { from, target: landing, duration, .. } => {
+                *from = origin;
+                *landing = target;
+                self.velocity = (target - origin) / *duration;
+            }
+            // beams have no speed, but keep the direction for rotation
+            Motion::Beam { .. } => self.velocity = direction,
+            _ => {}
+        }
+
+        self
+    }
+
+    pub fn velocity(&self) -> Vec2 {
+        self.velocity
+    }
+
+    // The sprite is drawn facing left, so turn it from there
+    pub fn rotation(&self) -> Quat {
+        Quat::from_rotation_z(self.velocity.y.atan2(self.velocity.x) - PI)
+    }
+
+    pub fn damage(&self) -> Damage {
+        Damage::new(self.damage, self.spell.damage_kind())
+    }
+
+    pub fn spell(&self) -> Spell {
+        self.spell
+    }
+
+    // Lobbed shots and beams do their damage in the movement system instead of on contact
+    pub fn hits_on_contact(&self) -> bool {
+        !matches!(self.motion, Motion::Arcing { .. } | Motion::Beam { .. })
+    }
+
+    pub fn last_hit(&self) -> Option<Entity> {
+        match self.motion {
+            Motion::Bouncing { last_hit, .. } => last_hit,
+            _ => None,
+        }
+    }
+
+    // After striking `hit`, pick the next target out of `candidates`. Returns
+    // false once there's nowhere left to go and the projectile is spent.
+    pub fn bounce(&mut self, hit: Entity, from: Vec2, candidates: &[(Entity, Vec2)]) -> bool {
+        let speed = self.velocity.length();
+
+        let (bounces_left, range, last_hit) = match &mut self.motion {
+            Motion::Bouncing { bounces_left, range, last_hit } if *bounces_left > 0 => (bounces_left, range, last_hit),
+            _ => return false,
+        };
+
+        let next = candidates
+            .iter()
+            .filter(|(entity, position)| *entity != hit && position.distance(from) <= *range)
+            .min_by(|a, b| a.1.distance(from).total_cmp(&b.1.distance(from)));
+
+        match next {
+            Some((_, position)) => {
+                *bounces_left -= 1;
+                *last_hit = Some(hit);
+                self.velocity = (*position - from).normalize_or_zero() * speed;
+                true
+            }
+            None => false,
+        }
     }
 
-    pub fn get_hitbox() -> Vec2 {
-        Vec2::new(100., 50.)
+    // Beams only get wider, everything else grows in both directions
+    pub fn size(&self) -> Vec2 {
+        match self.spell {
+            Spell::Beam => self.spell.size() * Vec2::new(1., self.scale),
+            _ => self.spell.size() * self.scale,
+        }
+    }
+
+    // Axis aligned box around the rotated sprite
+    pub fn hitbox(&self) -> Vec2 {
+        let size = self.size();
+        let direction = self.velocity.normalize_or_zero();
+        let (cos, sin) = (direction.x.abs(), direction.y.abs());
+
+        Vec2::new(size.x * cos + size.y * sin, size.x * sin + size.y * cos)
     }
 }
 
 #[derive(Component)]
 struct Fireball;
 
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct SelectedSpell(pub Spell);
+
+// Whether spells fly straight along the wizard's row or toward the mouse
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AimMode {
+    #[default]
+    Row,
+    Cursor,
+}
+
+impl AimMode {
+    pub fn name(&self) -> &str {
+        match self {
+            AimMode::Row => "Row",
+            AimMode::Cursor => "Mouse",
+        }
+    }
+}
+
+// What holding the cast key does
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CastMode {
+    // one cast per press
+    #[default]
+    Tap,
+    // keeps casting while held, as fast as the cooldown allows
+    Auto,
+    // builds up power while held and casts on release
+    Charge,
+}
+
+impl CastMode {
+    pub fn name(&self) -> &str {
+        match self {
+            CastMode::Tap => "Tap",
+            CastMode::Auto => "Auto",
+            CastMode::Charge => "Charge",
+        }
+    }
+}
+
+// The wizard's casting state: time until the next cast and any charge being held
+#[derive(Component, Default)]
+pub struct Caster {
+    cooldown: f32,
+    charge: Option<f32>,
+}
+
+impl Caster {
+    const MAX_CHARGE: f32 = 1.5;
+    const BAR_SIZE: Vec2 = Vec2::new(60., 6.);
+
+    fn ready(&self) -> bool {
+        self.cooldown <= 0.
+    }
+
+    pub fn restore_mana(&mut self) {
+        self.cooldown = 0.;
+    }
+
+    fn charge_fraction(&self) -> f32 {
+        self.charge.unwrap_or(0.) / Self::MAX_CHARGE
+    }
+}
+
+// Bar over the wizard's head that fills while a cast is charging
+#[derive(Component)]
+struct ChargeIndicator;
+
+// Ask for the selected spell to be cast, `charge` running from 0 to 1
+pub struct CastSpell {
+    pub charge: f32,
+}
+
+// Hand a spent projectile back to the pool for the next cast
+pub fn release_projectile(commands: &mut Commands, pool: &mut EntityPool<Projectile>, entity: Entity) {
+    commands
+        .entity(entity)
+        .remove::<(Projectile, Fireball, ParticleEmitter)>();
+    pool.release(commands, entity);
+}
+
+fn select_spell(
+    keyboard: Res<Input<KeyCode>>,
+    mut selected_spell: ResMut<SelectedSpell>,
+    profile: Res<Profile>,
+) {
+    let spells = [
+        (KeyCode::Key1, Spell::Fireball),
+        (KeyCode::Key2, Spell::FrostBolt),
+        (KeyCode::Key3, Spell::ArcaneMissile),
+        (KeyCode::Key4, Spell::Meteor),
+        (KeyCode::Key5, Spell::ChainOrb),
+        (KeyCode::Key6, Spell::Beam),
+    ];
+    for (key, spell) in spells {
+        let unlocked = spell.skill().is_none_or(|skill| profile.has_unlocked(skill));
+        if keyboard.just_pressed(key) && unlocked {
+            **selected_spell = spell;
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn shoot(
     mut commands: Commands,
+    mut pool: ResMut<EntityPool<Projectile>>,
     player_query: Query<(&Player, &Transform), With<Player>>,
-    keyboard: Res<Input<KeyCode>>,
+    mut casts: EventReader<CastSpell>,
+    player_stats: Res<PlayerStats>,
+    pattern_book: Res<PatternBook>,
+    selected_pattern: Res<SelectedPattern>,
     game_textures: Res<GameTextures>,
+    selected_spell: Res<SelectedSpell>,
+    aim_mode: Res<AimMode>,
+    windows: Res<Windows>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    mut stats: ResMut<RunStats>,
+    mut sounds: EventWriter<SoundEvent>,
 ) {
     let (_player, transform) = player_query.single();
-    if keyboard.just_pressed(KeyCode::Space) {
-        // spawn a fireball at player position
-        commands
-            .spawn(SpriteBundle {
-                texture: game_textures.fireball.clone(),
-                sprite: Sprite {
-                    custom_size: Some(Vec2::new(100., 50.)),
-                    ..default()
-                },
-                transform: Transform {
-                    translation: Vec3::new(transform.translation.x, transform.translation.y, 100.),
-                    ..default()
-                },
-                ..default()
-            })
-            .insert(Fireball)
-            .insert(Projectile::default());
+    for cast in casts.iter() {
+        let origin = transform.translation.truncate();
+
+        // straight down the row unless aiming at a cursor that's actually on screen
+        let target = match *aim_mode {
+            AimMode::Row => None,
+            AimMode::Cursor => {
+                let (camera, camera_transform) = camera_query.single();
+                cursor_world_position(&windows, camera, camera_transform)
+                    .filter(|target| target.distance_squared(origin) > 1.)
+            }
+        }
+        .unwrap_or(origin + Vec2::new(-Projectile::ROW_RANGE, 0.));
+
+        let spell = **selected_spell;
+        let pattern = pattern_book.get(**selected_pattern);
+        let shots = pattern.shots(player_stats.projectile_count() - 1);
+        let aim = target - origin;
+        let side = aim.perp().normalize_or_zero();
+
+        for shot in shots.iter() {
+            let origin = origin + side * shot.offset;
+            let target = origin + Vec2::from_angle(shot.rotation).rotate(aim);
+
+            let projectile = spell
+                .projectile()
+                .charged(cast.charge)
+                .scaled(pattern.size)
+                .empowered(player_stats.damage_multiplier() * pattern.damage)
+                .launch(origin, target);
+
+            spawn_spell(&mut commands, &mut pool, &game_textures, projectile, origin);
+        }
+
+        stats.shots_fired += shots.len();
+        sounds.send(SoundEvent(Sound::Cast));
     }
 }
 
-fn movement_projectile(
+// Put a launched projectile in the world at `origin`, for the wizard or anything else casting
+pub fn spawn_spell(
+    commands: &mut Commands,
+    pool: &mut EntityPool<Projectile>,
+    game_textures: &GameTextures,
+    projectile: Projectile,
+    origin: Vec2,
+) {
+    let spell = projectile.spell;
+
+    // beams start at the caster and run out along their direction
+    let (texture, translation) = match spell {
+        Spell::Beam => (
+            Handle::default(),
+            origin + projectile.velocity() * Projectile::BEAM_LENGTH / 2.,
+        ),
+        _ => (game_textures.fireball.clone(), origin),
+    };
+
+    let mut entity = pool.spawn(commands, SpriteBundle {
+        texture,
+        sprite: Sprite {
+            color: spell.color(),
+            custom_size: Some(projectile.size()),
+            ..default()
+        },
+        transform: Transform {
+            translation: translation.extend(100.),
+            rotation: projectile.rotation(),
+            ..default()
+        },
+        ..default()
+    });
+
+    match spell {
+        Spell::Fireball => {
+            entity
+                .insert(Fireball)
+                .insert(ParticleEmitter::new(ParticlePreset::FireballTrail));
+        }
+        Spell::Meteor => {
+            entity.insert(ParticleEmitter::new(ParticlePreset::FireballTrail));
+        }
+        Spell::FrostBolt => {
+            entity.insert(ParticleEmitter::new(ParticlePreset::FrostMist));
+        }
+        Spell::ArcaneMissile | Spell::ChainOrb | Spell::Beam => {}
+    };
+    entity.insert(projectile);
+}
+
+// Every kind of projectile motion is advanced here. Lobbed shots and beams
+// also deal their own damage since they never touch anything in flight.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn move_projectiles(
     mut commands: Commands,
-    mut projectile_query: Query<(Entity, &Projectile, &mut Transform)>,
+    mut pool: ResMut<EntityPool<Projectile>>,
+    mut projectile_query: Query<(Entity, &mut Projectile, &mut Transform)>,
+    enemy_query: Query<(Entity, &Enemy, &EnemyState, Option<&Elevation>, &Transform), Without<Projectile>>,
+    upgrades: Res<Upgrades>,
+    mut game_rng: ResMut<GameRng>,
+    mut hits: EventWriter<Hit>,
+    mut particles: EventWriter<ParticleBurst>,
+    mut shake: ResMut<ScreenShake>,
     time: Res<Time>,
 ) {
-    for (entity, projectile, mut transform) in projectile_query.iter_mut() {
-        transform.translation.x -= projectile.speed * time.delta_seconds();
+    let dt = time.delta_seconds();
+
+    for (entity, mut projectile, mut transform) in projectile_query.iter_mut() {
+        let spell = projectile.spell;
+        let position = transform.translation.truncate();
+
+        // living enemies this spell is able to touch
+        let targets = enemy_query.iter().filter(|(_, _, state, elevation, _)| {
+            **state != EnemyState::Death && spell.can_hit(elevation.copied().unwrap_or_default())
+        });
+
+        let mut hit = |target: Entity, at: Vec2, damage: Damage, style: DamageStyle| {
+            hits.send(Hit {
+                target,
+                position: at,
+                damage: damage.roll_crit(game_rng.rng(), upgrades.crit_chance(), upgrades.crit_multiplier()),
+                spell: Some(spell),
+                style,
+            });
+        };
 
-        if transform.translation.x < -800. {
-            commands.entity(entity).despawn();
+        let velocity = projectile.velocity;
+        let damage = projectile.damage();
+        let projectile_size = projectile.size();
+
+        match &mut projectile.motion {
+            Motion::Straight | Motion::Bouncing { .. } => {}
+            Motion::Homing { turn_rate } => {
+                let nearest = targets
+                    .map(|(.., t)| t.translation.truncate())
+                    .min_by(|a, b| a.distance(position).total_cmp(&b.distance(position)));
+
+                // no direction to turn from or towards when it's stopped or sat on its target
+                let nearest = nearest.filter(|nearest| {
+                    velocity.length_squared() > f32::EPSILON && (*nearest - position).length_squared() > f32::EPSILON
+                });
+                if let Some(nearest) = nearest {
+                    let wanted = velocity.angle_between(nearest - position);
+                    let turn = wanted.clamp(-*turn_rate * dt, *turn_rate * dt);
+                    projectile.velocity = Vec2::from_angle(turn).rotate(velocity);
+                }
+            }
+            Motion::Arcing { from, target, elapsed, duration, height, radius } => {
+                *elapsed += dt;
+                let t = (*elapsed / *duration).min(1.);
+                let ground = from.lerp(*target, t);
+                transform.translation.x = ground.x;
+                transform.translation.y = ground.y + *height * (PI * t).sin();
+
+                if t >= 1. {
+                    for (target_entity, enemy, .., t) in targets {
+                        let at = t.translation.truncate();
+                        // anything whose hurtbox overlaps the blast circle gets caught
+                        if at.distance(*target) <= *radius + enemy.hurtbox().min_element() / 2. {
+                            hit(target_entity, at, damage, DamageStyle::Normal);
+                        }
+                    }
+
+                    particles.send(ParticleBurst {
+                        preset: ParticlePreset::ImpactSparks,
+                        position: *target,
+                        count: Some(40),
+                    });
+                    shake.add_trauma(0.3);
+                    release_projectile(&mut commands, &mut pool, entity);
+                }
+                continue;
+            }
+            Motion::Beam { tick, lifetime } => {
+                lifetime.tick(time.delta());
+                if tick.tick(time.delta()).just_finished() {
+                    let direction = velocity;
+                    let width = projectile_size.y;
+                    let origin = position - direction * Projectile::BEAM_LENGTH / 2.;
+                    let per_tick = Damage {
+                        amount: damage.amount * tick.duration().as_secs_f32(),
+                        ..damage
+                    };
+
+                    for (target_entity, enemy, .., t) in targets {
+                        let at = t.translation.truncate();
+                        let offset = at - origin;
+                        let along = offset.dot(direction);
+                        let across = offset.perp_dot(direction).abs();
+
+                        if (0. ..=Projectile::BEAM_LENGTH).contains(&along)
+                            && across <= enemy.hurtbox().y / 2. + width / 2.
+                        {
+                            hit(target_entity, at, per_tick, DamageStyle::Status);
+                        }
+                    }
+                }
+
+                if lifetime.finished() {
+                    release_projectile(&mut commands, &mut pool, entity);
+                }
+                continue;
+            }
+        }
+
+        transform.translation += (projectile.velocity * dt).extend(0.);
+        transform.rotation = projectile.rotation();
+
+        // gone once it leaves the screen through any edge, or if its motion broke down
+        let position = transform.translation.truncate();
+        if !position.is_finite() || position.abs().cmpgt(ARENA_HALF_SIZE).any() {
+            release_projectile(&mut commands, &mut pool, entity);
         }
     }
 }
 
-fn add_wealth_system(query: Query<(&Enemy, &EnemyState)>, mut player_query: Query<&mut Player>) {
-    let mut player = player_query.single_mut();
+fn toggle_cast_mode(keyboard: Res<Input<KeyCode>>, mut cast_mode: ResMut<CastMode>, mut caster_query: Query<&mut Caster>) {
+    if keyboard.just_pressed(KeyCode::C) {
+        *cast_mode = match *cast_mode {
+            CastMode::Tap => CastMode::Auto,
+            CastMode::Auto => CastMode::Charge,
+            CastMode::Charge => CastMode::Tap,
+        };
+        caster_query.single_mut().charge = None;
+    }
+}
 
-    for (_enemy, state) in query.iter() {
-        match state {
-            EnemyState::Death => player.add_wealth(1),
-            _ => {}
+// Turn the cast key into casts according to the cast mode and cooldown
+#[allow(clippy::too_many_arguments)]
+fn trigger_casts(
+    keyboard: Res<Input<KeyCode>>,
+    cast_mode: Res<CastMode>,
+    selected_spell: Res<SelectedSpell>,
+    mut caster_query: Query<&mut Caster, Without<Dying>>,
+    player_stats: Res<PlayerStats>,
+    repair: Res<WallRepair>,
+    mut casts: EventWriter<CastSpell>,
+    time: Res<Time>,
+) {
+    // the fallen don't cast
+    let mut caster = match caster_query.get_single_mut() {
+        Ok(caster) => caster,
+        Err(_) => return,
+    };
+    caster.cooldown -= time.delta_seconds();
+
+    // both hands are busy with the wall, and a held charge is lost
+    if repair.channeling() {
+        caster.charge = None;
+        return;
+    }
+
+    let charge = match *cast_mode {
+        CastMode::Tap => (keyboard.just_pressed(KeyCode::Space) && caster.ready()).then_some(0.),
+        CastMode::Auto => (keyboard.pressed(KeyCode::Space) && caster.ready()).then_some(0.),
+        CastMode::Charge => {
+            if keyboard.pressed(KeyCode::Space) {
+                let held = caster.charge.unwrap_or(0.) + time.delta_seconds();
+                caster.charge = Some(held.min(Caster::MAX_CHARGE));
+            }
+
+            // a release during the cooldown lets the charge fizzle
+            if keyboard.just_released(KeyCode::Space) {
+                let charge = caster.charge_fraction();
+                caster.charge = None;
+                caster.ready().then_some(charge)
+            } else {
+                None
+            }
+        }
+    };
+
+    if let Some(charge) = charge {
+        caster.cooldown = selected_spell.cooldown() / player_stats.fire_rate();
+        casts.send(CastSpell { charge });
+    }
+}
+
+fn update_charge_indicator(
+    caster_query: Query<(&Caster, &Children)>,
+    mut indicator_query: Query<(&mut Sprite, &mut Visibility), With<ChargeIndicator>>,
+    selected_spell: Res<SelectedSpell>,
+    time: Res<Time>,
+) {
+    let (caster, children) = caster_query.single();
+    let fraction = caster.charge_fraction();
+
+    for child in children.iter() {
+        if let Ok((mut sprite, mut visibility)) = indicator_query.get_mut(*child) {
+            visibility.is_visible = caster.charge.is_some();
+            sprite.custom_size = Some(Vec2::new(Caster::BAR_SIZE.x * fraction, Caster::BAR_SIZE.y));
+
+            // fills in the spell's colour and flickers once it's fully charged
+            sprite.color = if fraction >= 1. && (time.elapsed_seconds() * 10.).sin() > 0. {
+                Color::WHITE
+            } else {
+                let mut color = selected_spell.color();
+                color.set_a(1.);
+                color
+            };
+        }
+    }
+}
+
+fn toggle_aim_mode(keyboard: Res<Input<KeyCode>>, mut aim_mode: ResMut<AimMode>) {
+    if keyboard.just_pressed(KeyCode::M) {
+        *aim_mode = match *aim_mode {
+            AimMode::Row => AimMode::Cursor,
+            AimMode::Cursor => AimMode::Row,
+        };
+    }
+}
+
+
+fn hurt_player(
+    mut commands: Commands,
+    mut events: EventReader<PlayerHurt>,
+    mut player_query: Query<(Entity, &mut Player, &Transform), Without<Dying>>,
+    mut shake: ResMut<ScreenShake>,
+    mut sounds: EventWriter<SoundEvent>,
+    cheats: Res<Cheats>,
+) {
+    let (entity, mut player, transform) = match player_query.get_single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+
+    for event in events.iter() {
+        if player.invulnerable > 0. || player.health <= 0. || cheats.god() {
+            continue;
+        }
+
+        player.health -= event.amount;
+        player.invulnerable = Player::INVULNERABLE_TIME;
+        player.knockback = (transform.translation.truncate() - event.from).normalize_or_zero() * Player::KNOCKBACK;
+        shake.add_trauma(0.4);
+        sounds.send(SoundEvent(Sound::Impact));
+
+        if player.health <= 0. {
+            player.health = 0.;
+            commands.entity(entity).insert(Dying(Timer::from_seconds(1.5, TimerMode::Once)));
         }
     }
 }
 
+// Knockback, the drift back into place afterwards and the blink while invulnerable
+fn recover_player(
+    mut player_query: Query<(&mut Player, &mut Transform, &mut Sprite), Without<Dying>>,
+    time: Res<Time>,
+) {
+    let (mut player, mut transform, mut sprite) = match player_query.get_single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let delta = time.delta_seconds();
+    let half = player.size() / 2.;
+
+    let knockback = player.knockback;
+    transform.translation.x += knockback.x * delta;
+    transform.translation.y += knockback.y * delta;
+    player.knockback *= (1. - 8. * delta).max(0.);
+
+    // stay behind the wall and inside the lanes
+    transform.translation.x += (Player::HOME_X - transform.translation.x) * (3. * delta).min(1.);
+    transform.translation.x = transform
+        .translation
+        .x
+        .clamp(Wall::LEFT + Wall::SIZE / 2. + half.x, ARENA_HALF_SIZE.x - half.x);
+    transform.translation.y = transform.translation.y.clamp(-385. + half.y, 385. - half.y);
+
+    player.invulnerable = (player.invulnerable - delta).max(0.);
+    let blink = player.invulnerable > 0. && (player.invulnerable * 20.).sin() > 0.;
+    sprite.color.set_a(if blink { 0.3 } else { 1. });
+}
+
+// Spin and shrink away, then end the run
+fn wizard_fallen(
+    mut player_query: Query<(&mut Dying, &mut Transform, &mut Sprite)>,
+    mut stats: ResMut<RunStats>,
+    mut state: ResMut<State<AppState>>,
+    time: Res<Time>,
+) {
+    let (mut dying, mut transform, mut sprite) = match player_query.get_single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+
+    dying.0.tick(time.delta());
+    let remaining = 1. - dying.0.percent();
+    transform.rotation = Quat::from_rotation_z(dying.0.percent() * 2. * PI);
+    transform.scale = Vec3::splat(remaining.max(0.1));
+    sprite.color = Color::rgba(1., 0.4, 0.4, remaining);
+
+    if dying.0.just_finished() {
+        stats.wizard_fallen = true;
+        let _ = state.set(AppState::GameOver);
+    }
+}
@@ -0,0 +1,58 @@
+use bevy::{ecs::system::EntityCommands, prelude::*};
+use std::{collections::HashSet, marker::PhantomData};
+
+// Marks an entity that is parked in a pool, hidden and waiting to be reused
+#[derive(Component)]
+pub struct Pooled;
+
+// Recycles entities of one kind instead of despawning them. Callers strip the
+// components that make an entity show up in gameplay queries before releasing
+// it, and insert them again on spawn.
+#[derive(Resource)]
+pub struct EntityPool<T> {
+    free: Vec<Entity>,
+    parked: HashSet<Entity>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for EntityPool<T> {
+    fn default() -> Self {
+        Self {
+            free: Vec::new(),
+            parked: HashSet::new(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> EntityPool<T> {
+    pub fn spawn<'w, 's, 'a, B: Bundle>(
+        &mut self,
+        commands: &'a mut Commands<'w, 's>,
+        bundle: B,
+    ) -> EntityCommands<'w, 's, 'a> {
+        match self.free.pop() {
+            Some(entity) => {
+                self.parked.remove(&entity);
+
+                let mut entity_commands = commands.entity(entity);
+                entity_commands.remove::<Pooled>().insert(bundle);
+                entity_commands
+            }
+            None => commands.spawn(bundle),
+        }
+    }
+
+    pub fn release(&mut self, commands: &mut Commands, entity: Entity) {
+        // releasing twice would hand the same entity out to two spawns
+        if !self.parked.insert(entity) {
+            return;
+        }
+
+        commands
+            .entity(entity)
+            .insert(Pooled)
+            .insert(Visibility { is_visible: false });
+        self.free.push(entity);
+    }
+}
@@ -0,0 +1,240 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs};
+
+use crate::commons::AppState;
+use crate::enemy::Wave;
+use crate::stats::RunStats;
+use crate::wall::Wall;
+
+pub struct ProfilePlugin;
+
+impl Plugin for ProfilePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Profile::load())
+            .init_resource::<EssenceAwarded>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::GameOver).with_system(award_essence.label(AwardEssence)),
+            );
+    }
+}
+
+// Kept apart from any settings file so wiping one never touches the other
+pub const PROFILE_PATH: &str = "profile.ron";
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Skill {
+    StartingWealth,
+    WallHealth,
+    FrostBolt,
+    ArcaneMissile,
+    Meteor,
+    ChainOrb,
+    Beam,
+    ArcaneTower,
+    FrostTower,
+}
+
+impl Skill {
+    pub const ALL: [Skill; 9] = [
+        Skill::StartingWealth,
+        Skill::WallHealth,
+        Skill::FrostBolt,
+        Skill::ArcaneMissile,
+        Skill::Meteor,
+        Skill::ChainOrb,
+        Skill::Beam,
+        Skill::ArcaneTower,
+        Skill::FrostTower,
+    ];
+
+    pub fn name(&self) -> &str {
+        match self {
+            Skill::StartingWealth => "Starting Gold",
+            Skill::WallHealth => "Reinforced Wall",
+            Skill::FrostBolt => "Frost Bolt",
+            Skill::ArcaneMissile => "Arcane Missile",
+            Skill::Meteor => "Meteor",
+            Skill::ChainOrb => "Chain Orb",
+            Skill::Beam => "Beam",
+            Skill::ArcaneTower => "Arcane Turret",
+            Skill::FrostTower => "Frost Spire",
+        }
+    }
+
+    pub fn max_level(&self) -> u32 {
+        match self {
+            Skill::StartingWealth => 5,
+            Skill::WallHealth => 5,
+            // spells and towers are simply learned or not
+            _ => 1,
+        }
+    }
+
+    // The skills, and the levels in them, that have to come first
+    pub fn requires(&self) -> &'static [(Skill, u32)] {
+        match self {
+            Skill::StartingWealth | Skill::WallHealth | Skill::FrostBolt => &[],
+            Skill::ArcaneMissile => &[(Skill::FrostBolt, 1)],
+            Skill::Meteor | Skill::ChainOrb => &[(Skill::ArcaneMissile, 1)],
+            Skill::Beam => &[(Skill::Meteor, 1), (Skill::ChainOrb, 1)],
+            // towers are mounted on the wall, so it has to be able to take them
+            Skill::ArcaneTower => &[(Skill::WallHealth, 2), (Skill::ArcaneMissile, 1)],
+            Skill::FrostTower => &[(Skill::ArcaneTower, 1), (Skill::FrostBolt, 1)],
+        }
+    }
+
+    // Essence needed to go from `level` to `level + 1`
+    pub fn cost(&self, level: u32) -> u32 {
+        let base = match self {
+            Skill::StartingWealth => 20,
+            Skill::WallHealth => 25,
+            Skill::FrostBolt => 60,
+            Skill::ArcaneMissile => 80,
+            Skill::Meteor => 120,
+            Skill::ChainOrb => 100,
+            Skill::Beam => 160,
+            Skill::ArcaneTower => 80,
+            Skill::FrostTower => 120,
+        };
+
+        base * (level + 1)
+    }
+}
+
+#[derive(Resource, Default, Serialize, Deserialize)]
+pub struct Profile {
+    essence: u32,
+    skills: HashMap<Skill, u32>,
+}
+
+impl Profile {
+    pub fn load() -> Self {
+        match fs::read_to_string(PROFILE_PATH) {
+            Ok(text) => ron::from_str(&text).unwrap_or_else(|err| {
+                error!("Failed to parse profile, starting fresh: {}", err);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self) {
+        let text = match ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()) {
+            Ok(text) => text,
+            Err(err) => {
+                error!("Failed to serialize profile: {}", err);
+                return;
+            }
+        };
+
+        if let Err(err) = fs::write(PROFILE_PATH, text) {
+            error!("Failed to save profile: {}", err);
+        }
+    }
+
+    pub fn essence(&self) -> u32 {
+        self.essence
+    }
+
+    pub fn level(&self, skill: Skill) -> u32 {
+        self.skills.get(&skill).copied().unwrap_or(0)
+    }
+
+    pub fn has_unlocked(&self, skill: Skill) -> bool {
+        self.level(skill) > 0
+    }
+
+    pub fn meets_requirements(&self, skill: Skill) -> bool {
+        skill
+            .requires()
+            .iter()
+            .all(|(required, level)| self.level(*required) >= *level)
+    }
+
+    pub fn try_unlock(&mut self, skill: Skill) -> bool {
+        let level = self.level(skill);
+        let cost = skill.cost(level);
+
+        if level >= skill.max_level() || self.essence < cost || !self.meets_requirements(skill) {
+            return false;
+        }
+
+        self.essence -= cost;
+        self.skills.insert(skill, level + 1);
+        true
+    }
+
+    pub fn starting_wealth(&self) -> usize {
+        self.level(Skill::StartingWealth) as usize * 100
+    }
+
+    pub fn wall_max_health(&self) -> f32 {
+        Wall::default().max_health() + self.level(Skill::WallHealth) as f32 * 25.
+    }
+}
+
+#[derive(SystemLabel)]
+pub struct AwardEssence;
+
+// Essence handed out for the run that just ended, shown on the game over screen
+#[derive(Resource, Default)]
+pub struct EssenceAwarded(pub u32);
+
+fn award_essence(
+    mut profile: ResMut<Profile>,
+    mut awarded: ResMut<EssenceAwarded>,
+    stats: Res<RunStats>,
+    wave: Res<Wave>,
+) {
+    let essence = (wave.index() * 5 + stats.total_kills() / 5) as u32;
+
+    profile.essence += essence;
+    profile.save();
+
+    awarded.0 = essence;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_essence(essence: u32) -> Profile {
+        Profile {
+            essence,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn skills_wait_for_their_requirements() {
+        let mut profile = with_essence(1000);
+
+        assert!(!profile.try_unlock(Skill::ArcaneTower));
+        assert_eq!(profile.essence(), 1000);
+
+        assert!(profile.try_unlock(Skill::WallHealth));
+        assert!(profile.try_unlock(Skill::WallHealth));
+        assert!(!profile.try_unlock(Skill::ArcaneTower));
+
+        assert!(profile.try_unlock(Skill::FrostBolt));
+        assert!(profile.try_unlock(Skill::ArcaneMissile));
+        assert!(profile.try_unlock(Skill::ArcaneTower));
+        assert!(profile.try_unlock(Skill::FrostTower));
+
+        // the beam needs both spells before it
+        assert!(profile.try_unlock(Skill::Meteor));
+        assert!(!profile.try_unlock(Skill::Beam));
+        assert!(profile.try_unlock(Skill::ChainOrb));
+        assert!(profile.try_unlock(Skill::Beam));
+    }
+
+    #[test]
+    fn unlocking_stops_at_max_level() {
+        let mut profile = with_essence(1000);
+
+        assert!(profile.try_unlock(Skill::FrostBolt));
+        assert!(!profile.try_unlock(Skill::FrostBolt));
+        assert_eq!(profile.level(Skill::FrostBolt), 1);
+    }
+}
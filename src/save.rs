@@ -0,0 +1,450 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, io, time::Duration};
+
+use crate::boss::spawn_boss;
+use crate::commons::{AppState, GameRng, GameTextures};
+use crate::economy::{WaveBonus, Wallet};
+use crate::enemy::{release_enemy, spawn_enemy_at, Enemy, EnemyKind, EnemySpawnTimer, EnemyState, Wave};
+use crate::loot::Pickup;
+use crate::player::{release_projectile, Player, Projectile, Upgrades};
+use crate::pool::EntityPool;
+use crate::stats::RunStats;
+use crate::traps::{spawn_trap, Trap, TrapGhost, TrapKind, TrapPlacement};
+use crate::wall::{Wall, WallModules};
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(AppState::Playing)
+                .with_system(save_game)
+                .with_system(load_game),
+        );
+    }
+}
+
+pub const SAVE_PATH: &str = "savegame.ron";
+
+// Bump this whenever the layout of `SaveGame` changes and add a step to `migrate`
+pub const SAVE_VERSION: u32 = 3;
+
+#[derive(Serialize, Deserialize)]
+pub struct SaveGame {
+    version: u32,
+    wall: Wall,
+    #[serde(default)]
+    wall_modules: WallModules,
+    player: Player,
+    player_y: f32,
+    upgrades: Upgrades,
+    wave: Wave,
+    spawn_timer_elapsed: f32,
+    rng_seed: u64,
+    enemies: Vec<EnemySave>,
+    #[serde(default)]
+    stats: RunStats,
+    #[serde(default)]
+    wallet: Wallet,
+    #[serde(default)]
+    traps: Vec<TrapSave>,
+}
+
+// Version 1 kept the gold on the wizard, before there was a wallet
+#[derive(Deserialize)]
+struct LegacyPlayer {
+    wealth: usize,
+}
+
+#[derive(Deserialize)]
+struct LegacySave {
+    player: LegacyPlayer,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EnemySave {
+    position: Vec2,
+    state: EnemyState,
+    health: f32,
+    #[serde(default)]
+    kind: EnemyKind,
+    #[serde(default)]
+    max_health: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TrapSave {
+    kind: TrapKind,
+    position: Vec2,
+    // what's left of a barricade
+    health: f32,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Serialize(ron::Error),
+    Deserialize(ron::error::SpannedError),
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Io(err) => write!(f, "{}", err),
+            SaveError::Serialize(err) => write!(f, "{}", err),
+            SaveError::Deserialize(err) => write!(f, "{}", err),
+            SaveError::UnsupportedVersion(version) => {
+                write!(f, "unsupported save version {}", version)
+            }
+        }
+    }
+}
+
+impl SaveGame {
+    pub fn write(&self, path: &str) -> Result<(), SaveError> {
+        fs::write(path, self.to_ron()?).map_err(SaveError::Io)
+    }
+
+    pub fn read(path: &str) -> Result<Self, SaveError> {
+        let text = fs::read_to_string(path).map_err(SaveError::Io)?;
+        Self::from_ron(&text)
+    }
+
+    fn to_ron(&self) -> Result<String, SaveError> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).map_err(SaveError::Serialize)
+    }
+
+    fn from_ron(text: &str) -> Result<Self, SaveError> {
+        let save: SaveGame = ron::from_str(text).map_err(SaveError::Deserialize)?;
+        migrate(save, text)
+    }
+}
+
+// Upgrade an older save to the current layout, one version at a time.
+// Fields added in later versions should be `#[serde(default)]` so older
+// files still parse, then get filled in here.
+fn migrate(mut save: SaveGame, text: &str) -> Result<SaveGame, SaveError> {
+    if save.version == 0 || save.version > SAVE_VERSION {
+        return Err(SaveError::UnsupportedVersion(save.version));
+    }
+
+    if save.version == 1 {
+        let legacy: LegacySave = ron::from_str(text).map_err(SaveError::Deserialize)?;
+        save.wallet = Wallet::with_balance(legacy.player.wealth);
+    }
+
+    // version 2 didn't keep traps, so those runs load with an empty field
+
+    // saves from before enemies kept their max health get their kind's default
+    for enemy in save.enemies.iter_mut() {
+        if enemy.max_health <= 0. {
+            enemy.max_health = Enemy::new(enemy.kind).max_health();
+        }
+    }
+
+    save.version = SAVE_VERSION;
+    Ok(save)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn save_game(
+    keyboard: Res<Input<KeyCode>>,
+    wall: Res<Wall>,
+    wall_modules: Res<WallModules>,
+    upgrades: Res<Upgrades>,
+    wave: Res<Wave>,
+    stats: Res<RunStats>,
+    wallet: Res<Wallet>,
+    spawn_timer: Res<EnemySpawnTimer>,
+    mut game_rng: ResMut<GameRng>,
+    player_query: Query<(&Player, &Transform)>,
+    enemy_query: Query<(&Enemy, &EnemyState, &Transform)>,
+    trap_query: Query<(&Trap, &Transform)>,
+) {
+    if !keyboard.just_pressed(KeyCode::F5) {
+        return;
+    }
+
+    let (player, player_transform) = player_query.single();
+
+    let enemies = enemy_query
+        .iter()
+        .filter(|(_, state, _)| **state != EnemyState::Death)
+        .map(|(enemy, state, transform)| EnemySave {
+            position: transform.translation.truncate(),
+            // a leap can't be resumed half way, so land it where it started
+            state: match state {
+                EnemyState::Leap => EnemyState::Walk,
+                _ => *state,
+            },
+            health: enemy.health(),
+            kind: enemy.kind(),
+            max_health: enemy.max_health(),
+        })
+        .collect();
+
+    let traps = trap_query
+        .iter()
+        .map(|(trap, transform)| TrapSave {
+            kind: trap.kind(),
+            position: transform.translation.truncate(),
+            health: trap.health(),
+        })
+        .collect();
+
+    let save = SaveGame {
+        version: SAVE_VERSION,
+        wall: wall.clone(),
+        wall_modules: wall_modules.clone(),
+        player: player.clone(),
+        player_y: player_transform.translation.y,
+        upgrades: upgrades.clone(),
+        wave: wave.clone(),
+        spawn_timer_elapsed: spawn_timer.elapsed_secs(),
+        rng_seed: game_rng.reseed(),
+        enemies,
+        stats: stats.clone(),
+        wallet: wallet.clone(),
+        traps,
+    };
+
+    match save.write(SAVE_PATH) {
+        Ok(()) => info!("Saved game to {}", SAVE_PATH),
+        Err(err) => error!("Failed to save game: {}", err),
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn load_game(
+    mut commands: Commands,
+    keyboard: Res<Input<KeyCode>>,
+    game_textures: Res<GameTextures>,
+    mut enemy_pool: ResMut<EntityPool<Enemy>>,
+    mut projectile_pool: ResMut<EntityPool<Projectile>>,
+    mut spawn_timer: ResMut<EnemySpawnTimer>,
+    mut player_query: Query<(&mut Player, &mut Transform)>,
+    enemy_query: Query<Entity, With<Enemy>>,
+    projectile_query: Query<Entity, With<Projectile>>,
+    field_query: Query<Entity, Or<(With<Trap>, With<TrapGhost>, With<Pickup>)>>,
+) {
+    if !keyboard.just_pressed(KeyCode::F9) {
+        return;
+    }
+
+    let save = match SaveGame::read(SAVE_PATH) {
+        Ok(save) => save,
+        Err(err) => {
+            error!("Failed to load game: {}", err);
+            return;
+        }
+    };
+
+    // Clear out the current run before rebuilding it from the save
+    for entity in enemy_query.iter() {
+        release_enemy(&mut commands, &mut enemy_pool, entity);
+    }
+    for entity in projectile_query.iter() {
+        release_projectile(&mut commands, &mut projectile_pool, entity);
+    }
+    // otherwise traps bought after saving would stay on the field for free
+    for entity in field_query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let (mut player, mut player_transform) = player_query.single_mut();
+    *player = save.player;
+    player_transform.translation.y = save.player_y;
+
+    for enemy in save.enemies {
+        // a boss's max health grows with each boss wave, and its phase follows from health
+        let mut restored = Enemy::with_max_health(enemy.kind, enemy.max_health);
+        restored.apply_damage(enemy.max_health - enemy.health);
+
+        match enemy.kind {
+            EnemyKind::Boss => {
+                let entity = spawn_boss(&mut commands, &mut enemy_pool, &game_textures, enemy.position, restored);
+                commands.entity(entity).insert(enemy.state);
+            }
+            _ => {
+                spawn_enemy_at(
+                    &mut commands,
+                    &mut enemy_pool,
+                    &game_textures,
+                    enemy.position,
+                    restored,
+                    enemy.state,
+                );
+            }
+        }
+    }
+
+    for trap in save.traps {
+        spawn_trap(&mut commands, trap.kind, trap.position, trap.health);
+    }
+
+    spawn_timer.reset();
+    spawn_timer.set_elapsed(Duration::from_secs_f32(save.spawn_timer_elapsed));
+
+    commands.insert_resource(save.wall);
+    commands.insert_resource(save.wall_modules);
+    commands.insert_resource(save.upgrades);
+    commands.insert_resource(WaveBonus::from_wave(save.wave.index()));
+    commands.insert_resource(save.wave);
+    commands.insert_resource(save.stats);
+    commands.insert_resource(save.wallet);
+    commands.insert_resource(TrapPlacement::default());
+    commands.insert_resource(GameRng::from_seed(save.rng_seed));
+
+    info!("Loaded game from {}", SAVE_PATH);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::economy::Income;
+
+    fn sample_save() -> SaveGame {
+        let mut wall = Wall::default();
+        wall.apply_damage(35.);
+
+        let mut wave = Wave::default();
+        wave.skip();
+        wave.skip();
+
+        let mut wallet = Wallet::with_balance(120);
+        wallet.earn(Income::Bounty, 30);
+
+        SaveGame {
+            version: SAVE_VERSION,
+            wall,
+            wall_modules: WallModules::default(),
+            player: Player::default(),
+            player_y: -42.,
+            upgrades: Upgrades::default(),
+            wave,
+            spawn_timer_elapsed: 0.5,
+            rng_seed: 1234,
+            enemies: vec![
+                EnemySave {
+                    position: Vec2::new(-300., 80.),
+                    state: EnemyState::Walk,
+                    health: 60.,
+                    kind: EnemyKind::Grunt,
+                    max_health: 100.,
+                },
+                EnemySave {
+                    position: Vec2::new(400., 0.),
+                    state: EnemyState::Attack,
+                    health: 1500.,
+                    kind: EnemyKind::Boss,
+                    max_health: 2000.,
+                },
+            ],
+            stats: RunStats::default(),
+            wallet,
+            traps: vec![
+                TrapSave {
+                    kind: TrapKind::FireRune,
+                    position: Vec2::new(-100., 60.),
+                    health: 1.,
+                },
+                TrapSave {
+                    kind: TrapKind::Barricade,
+                    position: Vec2::new(0., -60.),
+                    health: 90.,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn round_trip_keeps_the_run() {
+        let text = sample_save().to_ron().unwrap();
+        let loaded = SaveGame::from_ron(&text).unwrap();
+
+        assert_eq!(loaded.version, SAVE_VERSION);
+        assert_eq!(loaded.wall.health(), 165.);
+        assert_eq!(loaded.wall.max_health(), 200.);
+        assert_eq!(loaded.player_y, -42.);
+        assert_eq!(loaded.wave.index(), 2);
+        assert_eq!(loaded.spawn_timer_elapsed, 0.5);
+        assert_eq!(loaded.rng_seed, 1234);
+        assert_eq!(loaded.wallet.balance(), 150);
+        assert_eq!(loaded.wallet.earned_from(Income::Bounty), 30);
+
+        assert_eq!(loaded.enemies.len(), 2);
+        let boss = &loaded.enemies[1];
+        assert!(boss.kind == EnemyKind::Boss);
+        assert!(boss.state == EnemyState::Attack);
+        assert_eq!(boss.position, Vec2::new(400., 0.));
+        assert_eq!(boss.health, 1500.);
+        assert_eq!(boss.max_health, 2000.);
+
+        assert_eq!(loaded.traps.len(), 2);
+        let barricade = &loaded.traps[1];
+        assert!(barricade.kind == TrapKind::Barricade);
+        assert_eq!(barricade.position, Vec2::new(0., -60.));
+        assert_eq!(barricade.health, 90.);
+    }
+
+    #[test]
+    fn version_two_loads_without_traps() {
+        let mut save = sample_save();
+        save.version = 2;
+        let text = save.to_ron().unwrap();
+        // cut the traps out, as a version 2 file never had them
+        let text = text[..text.find("traps:").unwrap()].to_string() + ")";
+
+        let loaded = SaveGame::from_ron(&text).unwrap();
+        assert_eq!(loaded.version, SAVE_VERSION);
+        assert!(loaded.traps.is_empty());
+        assert_eq!(loaded.wallet.balance(), 150);
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let mut save = sample_save();
+        save.version = SAVE_VERSION + 1;
+        let text = save.to_ron().unwrap();
+
+        assert!(matches!(
+            SaveGame::from_ron(&text),
+            Err(SaveError::UnsupportedVersion(version)) if version == SAVE_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn version_zero_is_rejected() {
+        let mut save = sample_save();
+        save.version = 0;
+        let text = save.to_ron().unwrap();
+
+        assert!(matches!(SaveGame::from_ron(&text), Err(SaveError::UnsupportedVersion(0))));
+    }
+
+    #[test]
+    fn version_one_moves_wealth_into_the_wallet() {
+        let text = "(
+            version: 1,
+            wall: (health: 150., max_health: 200.),
+            player: (wealth: 320),
+            player_y: 0.,
+            upgrades: (wall_max_health: 1),
+            wave: (index: 3, spawned: 4),
+            spawn_timer_elapsed: 1.,
+            rng_seed: 7,
+            enemies: [(position: (10., 20.), state: Walk, health: 40.)],
+        )";
+
+        let save = SaveGame::from_ron(text).unwrap();
+
+        assert_eq!(save.version, SAVE_VERSION);
+        assert_eq!(save.wallet.balance(), 320);
+        assert_eq!(save.wave.index(), 3);
+        // the enemy had no max health saved, so it gets its kind's default
+        assert!(save.enemies[0].kind == EnemyKind::Grunt);
+        assert_eq!(save.enemies[0].max_health, Enemy::new(EnemyKind::Grunt).max_health());
+    }
+}
@@ -0,0 +1,166 @@
+use bevy::{audio::AudioSink, prelude::*};
+use std::collections::HashMap;
+
+use crate::commons::GameSounds;
+use crate::enemy::Wave;
+
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SoundEvent>()
+            .init_resource::<Volume>()
+            .init_resource::<SoundCooldowns>()
+            .add_startup_system(start_music)
+            .add_system(play_sounds)
+            .add_system(intensify_music);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Sound {
+    Cast,
+    Impact,
+    EnemyDeath,
+    EnemyAttack,
+    WallHit,
+    WallCritical,
+}
+
+impl Sound {
+    fn category(&self) -> SoundCategory {
+        match self {
+            Sound::Cast | Sound::Impact => SoundCategory::Spell,
+            Sound::EnemyDeath | Sound::EnemyAttack => SoundCategory::Enemy,
+            Sound::WallHit | Sound::WallCritical => SoundCategory::Wall,
+        }
+    }
+
+    // Minimum seconds between two plays of the same sound
+    fn cooldown(&self) -> f32 {
+        match self {
+            Sound::Cast => 0.05,
+            Sound::Impact => 0.05,
+            Sound::EnemyDeath => 0.1,
+            Sound::EnemyAttack => 0.25,
+            Sound::WallHit => 0.2,
+            Sound::WallCritical => 5.,
+        }
+    }
+
+    fn handle(&self, game_sounds: &GameSounds) -> Handle<AudioSource> {
+        match self {
+            Sound::Cast => game_sounds.cast.clone(),
+            Sound::Impact => game_sounds.impact.clone(),
+            Sound::EnemyDeath => game_sounds.enemy_death.clone(),
+            Sound::EnemyAttack => game_sounds.enemy_attack.clone(),
+            Sound::WallHit => game_sounds.wall_hit.clone(),
+            Sound::WallCritical => game_sounds.wall_critical.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum SoundCategory {
+    Spell,
+    Enemy,
+    Wall,
+    Music,
+}
+
+pub struct SoundEvent(pub Sound);
+
+#[derive(Resource)]
+pub struct Volume {
+    pub master: f32,
+    pub spell: f32,
+    pub enemy: f32,
+    pub wall: f32,
+    pub music: f32,
+}
+
+impl Volume {
+    pub fn get(&self, category: SoundCategory) -> f32 {
+        let volume = match category {
+            SoundCategory::Spell => self.spell,
+            SoundCategory::Enemy => self.enemy,
+            SoundCategory::Wall => self.wall,
+            SoundCategory::Music => self.music,
+        };
+
+        self.master * volume
+    }
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Self {
+            master: 1.,
+            spell: 0.6,
+            enemy: 0.5,
+            wall: 0.7,
+            music: 0.4,
+        }
+    }
+}
+
+// Time each sound was last played, used to stop a burst of hits from clipping
+#[derive(Resource, Default)]
+struct SoundCooldowns(HashMap<Sound, f32>);
+
+#[derive(Resource)]
+struct Music(Handle<AudioSink>);
+
+fn play_sounds(
+    mut events: EventReader<SoundEvent>,
+    mut cooldowns: ResMut<SoundCooldowns>,
+    audio: Res<Audio>,
+    game_sounds: Res<GameSounds>,
+    volume: Res<Volume>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds();
+
+    for SoundEvent(sound) in events.iter() {
+        if let Some(last_played) = cooldowns.0.get(sound) {
+            if now - last_played < sound.cooldown() {
+                continue;
+            }
+        }
+        cooldowns.0.insert(*sound, now);
+
+        audio.play_with_settings(
+            sound.handle(&game_sounds),
+            PlaybackSettings::ONCE.with_volume(volume.get(sound.category())),
+        );
+    }
+}
+
+fn start_music(
+    mut commands: Commands,
+    audio: Res<Audio>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    game_sounds: Res<GameSounds>,
+    volume: Res<Volume>,
+) {
+    let weak_handle = audio.play_with_settings(
+        game_sounds.music.clone(),
+        PlaybackSettings::LOOP.with_volume(volume.get(SoundCategory::Music)),
+    );
+    commands.insert_resource(Music(audio_sinks.get_handle(weak_handle)));
+}
+
+// Music gets louder and faster as the waves go on
+fn intensify_music(
+    music: Res<Music>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    wave: Res<Wave>,
+    volume: Res<Volume>,
+) {
+    if let Some(sink) = audio_sinks.get(&music.0) {
+        let intensity = (wave.index() as f32 * 0.05).min(0.5);
+
+        sink.set_volume(volume.get(SoundCategory::Music) * (0.6 + intensity * 0.8));
+        sink.set_speed(1. + intensity * 0.4);
+    }
+}
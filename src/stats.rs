@@ -0,0 +1,179 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs};
+
+use crate::commons::AppState;
+use crate::enemy::{EnemyKilled, EnemyKind, Wave};
+use crate::player::Spell;
+
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RunStats>()
+            .init_resource::<LastScore>()
+            .insert_resource(Leaderboard::load())
+            .add_system_set(SystemSet::on_enter(AppState::Playing).with_system(reset_stats))
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(count_kills)
+                    .with_system(track_time),
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::GameOver).with_system(record_score.label(RecordScore)),
+            );
+    }
+}
+
+pub const LEADERBOARD_PATH: &str = "leaderboard.ron";
+
+#[derive(SystemLabel)]
+pub struct RecordScore;
+
+#[derive(Resource, Default, Clone, Serialize, Deserialize)]
+pub struct RunStats {
+    pub kills: HashMap<EnemyKind, usize>,
+    pub damage_by_spell: HashMap<Spell, f32>,
+    pub shots_fired: usize,
+    pub hits: usize,
+    pub wall_damage_taken: f32,
+    pub time_survived: f32,
+    // the run ended with the wizard down rather than the wall
+    #[serde(default)]
+    pub wizard_fallen: bool,
+}
+
+impl RunStats {
+    pub fn record_hit(&mut self, spell: Spell, damage: f32) {
+        self.hits += 1;
+        *self.damage_by_spell.entry(spell).or_insert(0.) += damage;
+    }
+
+    pub fn total_kills(&self) -> usize {
+        self.kills.values().sum()
+    }
+
+    pub fn total_damage(&self) -> f32 {
+        self.damage_by_spell.values().sum()
+    }
+
+    // A piercing hit can land more than once per shot, so cap at 100%
+    pub fn accuracy(&self) -> f32 {
+        if self.shots_fired == 0 {
+            return 0.;
+        }
+
+        (self.hits as f32 / self.shots_fired as f32).min(1.)
+    }
+
+    pub fn score(&self, waves: usize) -> u32 {
+        let base = self.total_kills() as f32 * 10.
+            + waves as f32 * 100.
+            + self.total_damage() / 10.
+            + self.time_survived;
+
+        // Reward clean aim and a well kept wall
+        let accuracy_bonus = 1. + self.accuracy() * 0.5;
+        let wall_penalty = self.wall_damage_taken / 5.;
+
+        (base * accuracy_bonus - wall_penalty).max(0.) as u32
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+    pub score: u32,
+    pub waves: usize,
+    pub kills: usize,
+    pub time_survived: f32,
+}
+
+#[derive(Resource, Default, Serialize, Deserialize)]
+pub struct Leaderboard {
+    entries: Vec<LeaderboardEntry>,
+}
+
+impl Leaderboard {
+    pub const SIZE: usize = 10;
+
+    pub fn load() -> Self {
+        match fs::read_to_string(LEADERBOARD_PATH) {
+            Ok(text) => ron::from_str(&text).unwrap_or_else(|err| {
+                error!("Failed to parse leaderboard, starting fresh: {}", err);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self) {
+        let text = match ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()) {
+            Ok(text) => text,
+            Err(err) => {
+                error!("Failed to serialize leaderboard: {}", err);
+                return;
+            }
+        };
+
+        if let Err(err) = fs::write(LEADERBOARD_PATH, text) {
+            error!("Failed to save leaderboard: {}", err);
+        }
+    }
+
+    pub fn entries(&self) -> &[LeaderboardEntry] {
+        &self.entries
+    }
+
+    // Returns the 1-based rank if the entry made the table
+    pub fn insert(&mut self, entry: LeaderboardEntry) -> Option<usize> {
+        let rank = self.entries.iter().position(|e| entry.score > e.score).unwrap_or(self.entries.len());
+
+        if rank >= Self::SIZE {
+            return None;
+        }
+
+        self.entries.insert(rank, entry);
+        self.entries.truncate(Self::SIZE);
+        Some(rank + 1)
+    }
+}
+
+// Result of the run that just ended, shown on the game over screen
+#[derive(Resource, Default)]
+pub struct LastScore {
+    pub score: u32,
+    pub rank: Option<usize>,
+}
+
+fn reset_stats(mut stats: ResMut<RunStats>) {
+    *stats = RunStats::default();
+}
+
+fn count_kills(mut events: EventReader<EnemyKilled>, mut stats: ResMut<RunStats>) {
+    for event in events.iter() {
+        *stats.kills.entry(event.kind).or_insert(0) += 1;
+    }
+}
+
+fn track_time(mut stats: ResMut<RunStats>, time: Res<Time>) {
+    stats.time_survived += time.delta_seconds();
+}
+
+fn record_score(
+    stats: Res<RunStats>,
+    wave: Res<Wave>,
+    mut leaderboard: ResMut<Leaderboard>,
+    mut last_score: ResMut<LastScore>,
+) {
+    let score = stats.score(wave.index());
+
+    let rank = leaderboard.insert(LeaderboardEntry {
+        score,
+        waves: wave.index(),
+        kills: stats.total_kills(),
+        time_survived: stats.time_survived,
+    });
+    leaderboard.save();
+
+    *last_score = LastScore { score, rank };
+}
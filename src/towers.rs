@@ -0,0 +1,160 @@
+use bevy::prelude::*;
+
+use crate::commons::{AppState, GameTextures};
+use crate::debug::timed;
+use crate::enemy::{Elevation, Enemy, EnemyState};
+use crate::player::{spawn_spell, Projectile, Spell};
+use crate::pool::EntityPool;
+use crate::profile::{Profile, Skill};
+use crate::sound::{Sound, SoundEvent};
+use crate::wall::Wall;
+
+pub struct TowerPlugin;
+
+impl Plugin for TowerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(AppState::Playing).with_system(reset_towers))
+            .add_system_set(SystemSet::on_update(AppState::Playing).with_system(timed(fire_towers)));
+    }
+}
+
+// Towers are unlocked in the skill tree and stand on the wall from the start of every run
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TowerKind {
+    // homing missiles at anything in range
+    ArcaneTurret,
+    // frost bolts that chill what they hit
+    FrostSpire,
+}
+
+impl TowerKind {
+    pub const ALL: [TowerKind; 2] = [TowerKind::ArcaneTurret, TowerKind::FrostSpire];
+
+    pub fn skill(&self) -> Skill {
+        match self {
+            TowerKind::ArcaneTurret => Skill::ArcaneTower,
+            TowerKind::FrostSpire => Skill::FrostTower,
+        }
+    }
+
+    fn spell(&self) -> Spell {
+        match self {
+            TowerKind::ArcaneTurret => Spell::ArcaneMissile,
+            TowerKind::FrostSpire => Spell::FrostBolt,
+        }
+    }
+
+    // seconds between shots
+    fn cooldown(&self) -> f32 {
+        match self {
+            TowerKind::ArcaneTurret => 1.2,
+            TowerKind::FrostSpire => 1.6,
+        }
+    }
+
+    // towers hit softer than the wizard casting the same spell
+    fn damage_multiplier(&self) -> f32 {
+        match self {
+            TowerKind::ArcaneTurret => 0.6,
+            TowerKind::FrostSpire => 0.5,
+        }
+    }
+
+    // one near each end of the wall
+    fn position(&self) -> Vec2 {
+        match self {
+            TowerKind::ArcaneTurret => Vec2::new(Wall::LEFT, 280.),
+            TowerKind::FrostSpire => Vec2::new(Wall::LEFT, -280.),
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            TowerKind::ArcaneTurret => Color::rgb(0.6, 0.35, 0.9),
+            TowerKind::FrostSpire => Color::rgb(0.5, 0.8, 1.),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Tower {
+    kind: TowerKind,
+    cooldown: f32,
+}
+
+impl Tower {
+    const SIZE: Vec2 = Vec2::new(40., 56.);
+    const RANGE: f32 = 900.;
+}
+
+fn reset_towers(mut commands: Commands, query: Query<Entity, With<Tower>>, profile: Res<Profile>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    for kind in TowerKind::ALL {
+        if !profile.has_unlocked(kind.skill()) {
+            continue;
+        }
+
+        commands
+            .spawn(SpriteBundle {
+                sprite: Sprite {
+                    color: kind.color(),
+                    custom_size: Some(Tower::SIZE),
+                    ..Default::default()
+                },
+                transform: Transform::from_translation(kind.position().extend(20.)),
+                ..Default::default()
+            })
+            .insert(Tower {
+                kind,
+                cooldown: kind.cooldown(),
+            });
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn fire_towers(
+    mut commands: Commands,
+    mut pool: ResMut<EntityPool<Projectile>>,
+    mut tower_query: Query<&mut Tower>,
+    enemy_query: Query<(&EnemyState, Option<&Elevation>, &Transform), With<Enemy>>,
+    game_textures: Res<GameTextures>,
+    mut sounds: EventWriter<SoundEvent>,
+    time: Res<Time>,
+) {
+    for mut tower in tower_query.iter_mut() {
+        tower.cooldown -= time.delta_seconds();
+        if tower.cooldown > 0. {
+            continue;
+        }
+
+        let spell = tower.kind.spell();
+        let origin = tower.kind.position();
+
+        // the closest living enemy in range that this tower's spell can touch
+        let target = enemy_query
+            .iter()
+            .filter(|(state, elevation, _)| {
+                **state != EnemyState::Death && spell.can_hit(elevation.copied().unwrap_or_default())
+            })
+            .map(|(.., transform)| transform.translation.truncate())
+            .filter(|position| position.distance(origin) <= Tower::RANGE)
+            .min_by(|a, b| a.distance(origin).total_cmp(&b.distance(origin)));
+
+        let target = match target {
+            Some(target) => target,
+            None => continue,
+        };
+
+        let projectile = spell
+            .projectile()
+            .empowered(tower.kind.damage_multiplier())
+            .launch(origin, target);
+        spawn_spell(&mut commands, &mut pool, &game_textures, projectile, origin);
+
+        tower.cooldown = tower.kind.cooldown();
+        sounds.send(SoundEvent(Sound::Cast));
+    }
+}
@@ -0,0 +1,469 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::ai::Brain;
+use crate::commons::{cursor_world_position, AppState};
+use crate::damage::{Damage, DamageKind, DamageStyle, Hit, ResolveHits};
+use crate::economy::{Expense, Wallet};
+use crate::enemy::{Elevation, Enemy, EnemyState};
+use crate::feedback::ScreenShake;
+use crate::particles::{ParticleBurst, ParticlePreset};
+use crate::sound::{Sound, SoundEvent};
+use crate::wall::Wall;
+
+pub struct TrapPlugin;
+
+impl Plugin for TrapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TrapPlacement>()
+            .add_event::<TrapTriggered>()
+            .add_event::<TrapStruck>()
+            .add_system_set(SystemSet::on_enter(AppState::Playing).with_system(reset_traps))
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(place_traps)
+                    .with_system(trigger_traps.after(TrapContact).before(ResolveHits))
+                    .with_system(damage_barricades),
+            );
+    }
+}
+
+// Runs the trap contact checks, so traps react the same frame they're touched
+#[derive(SystemLabel)]
+pub struct TrapContact;
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrapKind {
+    // slows anything wading through it
+    TarPit,
+    // bursts into flame the first time something steps on it
+    FireRune,
+    // stops the enemies in its lane until they break it down
+    Barricade,
+    // blows up on contact, catching flyers overhead too
+    Barrel,
+}
+
+impl TrapKind {
+    pub fn name(&self) -> &str {
+        match self {
+            TrapKind::TarPit => "Tar Pit",
+            TrapKind::FireRune => "Fire Rune",
+            TrapKind::Barricade => "Barricade",
+            TrapKind::Barrel => "Barrel",
+        }
+    }
+
+    pub fn cost(&self) -> usize {
+        match self {
+            TrapKind::TarPit => 150,
+            TrapKind::FireRune => 200,
+            TrapKind::Barricade => 250,
+            TrapKind::Barrel => 300,
+        }
+    }
+
+    fn size(&self) -> Vec2 {
+        match self {
+            TrapKind::TarPit => Vec2::new(90., 80.),
+            TrapKind::FireRune => Vec2::new(50., 50.),
+            TrapKind::Barricade => Vec2::new(30., 100.),
+            TrapKind::Barrel => Vec2::new(36., 36.),
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            TrapKind::TarPit => Color::rgb(0.15, 0.1, 0.05),
+            TrapKind::FireRune => Color::rgb(1., 0.45, 0.1),
+            TrapKind::Barricade => Color::rgb(0.55, 0.35, 0.15),
+            TrapKind::Barrel => Color::rgb(0.8, 0.15, 0.1),
+        }
+    }
+
+    fn health(&self) -> f32 {
+        match self {
+            TrapKind::Barricade => 150.,
+            _ => 1.,
+        }
+    }
+
+    // Traps sit on the ground, so like frost they pass under flyers and over burrowers
+    pub fn can_hit(&self, elevation: Elevation) -> bool {
+        elevation == Elevation::Ground
+    }
+}
+
+#[derive(Component)]
+pub struct Trap {
+    kind: TrapKind,
+    health: f32,
+}
+
+impl Trap {
+    const TAR_SLOW: f32 = 0.4;
+    const RUNE_RADIUS: f32 = 90.;
+    const RUNE_DAMAGE: f32 = 50.;
+    const BARREL_RADIUS: f32 = 160.;
+    const BARREL_DAMAGE: f32 = 80.;
+
+    pub fn kind(&self) -> TrapKind {
+        self.kind
+    }
+
+    pub fn size(&self) -> Vec2 {
+        self.kind.size()
+    }
+
+    pub fn health(&self) -> f32 {
+        self.health
+    }
+
+    // fades as it splinters
+    fn color(&self) -> Color {
+        let mut color = self.kind.color();
+        color.set_a(0.4 + 0.6 * (self.health / self.kind.health()).max(0.));
+        color
+    }
+}
+
+pub fn spawn_trap(commands: &mut Commands, kind: TrapKind, position: Vec2, health: f32) -> Entity {
+    let trap = Trap { kind, health };
+    commands
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                color: trap.color(),
+                custom_size: Some(kind.size()),
+                ..Default::default()
+            },
+            transform: Transform::from_translation(position.extend(5.)),
+            ..Default::default()
+        })
+        .insert(trap)
+        .id()
+}
+
+// An enemy is touching a trap this frame
+pub struct TrapTriggered {
+    pub trap: Entity,
+    pub enemy: Entity,
+}
+
+// An enemy hit a barricade it was attacking
+pub struct TrapStruck {
+    pub trap: Entity,
+    pub amount: f32,
+}
+
+// The trap the player has picked in the shop and is about to put down
+#[derive(Resource, Default)]
+pub struct TrapPlacement(Option<TrapKind>);
+
+impl TrapPlacement {
+    pub fn arm(&mut self, kind: TrapKind) {
+        self.0 = Some(kind);
+    }
+
+    pub fn armed(&self) -> Option<TrapKind> {
+        self.0
+    }
+}
+
+#[derive(Component)]
+pub struct TrapGhost;
+
+#[derive(Debug)]
+pub enum PlacementError {
+    OutsideField,
+    Occupied,
+    LaneFull,
+    LaneBarricaded,
+}
+
+// The field between the spawn and the wall is split into lanes across and
+// columns along it, and every trap sits in the middle of one of those cells
+struct Field;
+
+impl Field {
+    const LANE_HEIGHT: f32 = 110.;
+    // lanes run from -LANE_REACH to LANE_REACH with lane 0 through the middle
+    const LANE_REACH: i32 = 3;
+    const COLUMN_WIDTH: f32 = 100.;
+    const LEFT: f32 = -600.;
+    // keep clear of the wall so the spikes and attackers have room
+    const RIGHT: f32 = Wall::LEFT - Wall::SIZE - 50.;
+    const TRAPS_PER_LANE: usize = 3;
+
+    fn cell(position: Vec2) -> Result<(i32, i32), PlacementError> {
+        let lane = (position.y / Self::LANE_HEIGHT).round() as i32;
+        if lane.abs() > Self::LANE_REACH || position.x < Self::LEFT || position.x > Self::RIGHT {
+            return Err(PlacementError::OutsideField);
+        }
+
+        Ok((lane, (position.x / Self::COLUMN_WIDTH).round() as i32))
+    }
+
+    fn center(lane: i32, column: i32) -> Vec2 {
+        Vec2::new(column as f32 * Self::COLUMN_WIDTH, lane as f32 * Self::LANE_HEIGHT)
+    }
+
+    // Where a trap aimed at `position` would go, if it can go there at all
+    fn validate(kind: TrapKind, position: Vec2, placed: &[(TrapKind, Vec2)]) -> Result<Vec2, PlacementError> {
+        let (lane, column) = Self::cell(position)?;
+
+        let in_lane = placed
+            .iter()
+            .filter_map(|(kind, position)| Self::cell(*position).ok().map(|cell| (*kind, cell)))
+            .filter(|(_, (other_lane, _))| *other_lane == lane);
+
+        let mut count = 0;
+        let mut barricaded = false;
+        for (other, (_, other_column)) in in_lane {
+            if other_column == column {
+                return Err(PlacementError::Occupied);
+            }
+            count += 1;
+            barricaded |= other == TrapKind::Barricade;
+        }
+
+        if count >= Self::TRAPS_PER_LANE {
+            return Err(PlacementError::LaneFull);
+        }
+        if barricaded && kind == TrapKind::Barricade {
+            return Err(PlacementError::LaneBarricaded);
+        }
+
+        Ok(Self::center(lane, column))
+    }
+}
+
+// The middle of a lane, counting out from lane 0 through the centre of the field
+pub fn lane_y(lane: i32) -> Option<f32> {
+    (lane.abs() <= Field::LANE_REACH).then_some(Field::center(lane, 0).y)
+}
+
+#[allow(clippy::type_complexity)]
+fn reset_traps(
+    mut commands: Commands,
+    query: Query<Entity, Or<(With<Trap>, With<TrapGhost>)>>,
+    mut placement: ResMut<TrapPlacement>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    *placement = TrapPlacement::default();
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn place_traps(
+    mut commands: Commands,
+    mut placement: ResMut<TrapPlacement>,
+    trap_query: Query<(&Trap, &Transform), Without<TrapGhost>>,
+    mut ghost_query: Query<(Entity, &mut Transform, &mut Sprite), With<TrapGhost>>,
+    interaction_query: Query<&Interaction>,
+    mut wallet: ResMut<Wallet>,
+    windows: Res<Windows>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    mouse: Res<Input<MouseButton>>,
+    keyboard: Res<Input<KeyCode>>,
+) {
+    let kind = match placement.0 {
+        Some(kind) => kind,
+        None => {
+            for (entity, ..) in ghost_query.iter() {
+                commands.entity(entity).despawn();
+            }
+            return;
+        }
+    };
+
+    if mouse.just_pressed(MouseButton::Right) || keyboard.just_pressed(KeyCode::Escape) {
+        placement.0 = None;
+        return;
+    }
+
+    let (camera, camera_transform) = camera_query.single();
+    let cursor = match cursor_world_position(&windows, camera, camera_transform) {
+        Some(cursor) => cursor,
+        None => return,
+    };
+
+    let placed: Vec<(TrapKind, Vec2)> = trap_query
+        .iter()
+        .map(|(trap, transform)| (trap.kind, transform.translation.truncate()))
+        .collect();
+    let spot = Field::validate(kind, cursor, &placed);
+
+    // preview where it would land, red when it can't go there
+    let (ghost_position, ghost_color) = match &spot {
+        Ok(position) => (*position, Color::rgba(0.3, 1., 0.3, 0.5)),
+        Err(_) => (cursor, Color::rgba(1., 0.2, 0.2, 0.5)),
+    };
+    match ghost_query.get_single_mut() {
+        Ok((_, mut transform, mut sprite)) => {
+            transform.translation = ghost_position.extend(60.);
+            sprite.color = ghost_color;
+            sprite.custom_size = Some(kind.size());
+        }
+        Err(_) => {
+            commands
+                .spawn(SpriteBundle {
+                    sprite: Sprite {
+                        color: ghost_color,
+                        custom_size: Some(kind.size()),
+                        ..Default::default()
+                    },
+                    transform: Transform::from_translation(ghost_position.extend(60.)),
+                    ..Default::default()
+                })
+                .insert(TrapGhost);
+        }
+    }
+
+    // the click that picked the trap in the shop isn't a placement
+    let over_ui = interaction_query.iter().any(|interaction| *interaction != Interaction::None);
+    if !mouse.just_pressed(MouseButton::Left) || over_ui {
+        return;
+    }
+
+    let position = match spot {
+        Ok(position) => position,
+        Err(err) => {
+            info!("Can't place {} there: {:?}", kind.name(), err);
+            return;
+        }
+    };
+
+    if let Err(err) = wallet.try_spend(Expense::Trap, kind.cost()) {
+        info!("Can't afford {}: {}", kind.name(), err);
+        return;
+    }
+
+    spawn_trap(&mut commands, kind, position, kind.health());
+    placement.0 = None;
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn trigger_traps(
+    mut commands: Commands,
+    mut events: EventReader<TrapTriggered>,
+    trap_query: Query<(&Trap, &Transform), Without<Enemy>>,
+    mut enemy_query: Query<(Entity, &Enemy, &EnemyState, Option<&Elevation>, &mut Brain, &mut Transform)>,
+    mut hits: EventWriter<Hit>,
+    mut particles: EventWriter<ParticleBurst>,
+    mut shake: ResMut<ScreenShake>,
+    mut sounds: EventWriter<SoundEvent>,
+) {
+    let mut spent = HashSet::new();
+    let mut blocked = HashMap::new();
+    let mut explosions = Vec::new();
+
+    for event in events.iter() {
+        let (trap, trap_transform) = match trap_query.get(event.trap) {
+            Ok(trap) => trap,
+            Err(_) => continue,
+        };
+        let trap_position = trap_transform.translation.truncate();
+
+        match trap.kind {
+            TrapKind::TarPit => {
+                if let Ok((.., mut brain, _)) = enemy_query.get_mut(event.enemy) {
+                    brain.slow(Trap::TAR_SLOW);
+                }
+            }
+            TrapKind::Barricade => {
+                blocked.insert(event.enemy, (event.trap, trap_position.x - trap.size().x / 2.));
+            }
+            TrapKind::FireRune => {
+                if spent.insert(event.trap) {
+                    explosions.push((
+                        trap_position,
+                        Trap::RUNE_RADIUS,
+                        Damage::new(Trap::RUNE_DAMAGE, DamageKind::Fire),
+                        false,
+                        DamageStyle::Status,
+                    ));
+                    commands.entity(event.trap).despawn();
+                }
+            }
+            TrapKind::Barrel => {
+                if spent.insert(event.trap) {
+                    explosions.push((
+                        trap_position,
+                        Trap::BARREL_RADIUS,
+                        Damage::new(Trap::BARREL_DAMAGE, DamageKind::Physical),
+                        true,
+                        DamageStyle::Normal,
+                    ));
+                    commands.entity(event.trap).despawn();
+                }
+            }
+        }
+    }
+
+    for (center, radius, damage, reaches_air, style) in explosions {
+        for (entity, _, state, elevation, _, transform) in enemy_query.iter() {
+            let elevation = elevation.copied().unwrap_or_default();
+            let in_layer = elevation == Elevation::Ground || (reaches_air && elevation == Elevation::Air);
+            let position = transform.translation.truncate();
+
+            if *state != EnemyState::Death && in_layer && position.distance(center) <= radius {
+                hits.send(Hit {
+                    target: entity,
+                    position,
+                    damage,
+                    spell: None,
+                    style,
+                });
+            }
+        }
+
+        particles.send(ParticleBurst::new(ParticlePreset::ImpactSparks, center));
+        particles.send(ParticleBurst::new(ParticlePreset::DeathDust, center));
+        shake.add_trauma(if reaches_air { 0.4 } else { 0.2 });
+        sounds.send(SoundEvent(Sound::Impact));
+    }
+
+    // hold anything touching a barricade at its face until the barricade breaks
+    for (entity, enemy, .., mut brain, mut transform) in enemy_query.iter_mut() {
+        match blocked.get(&entity) {
+            Some((barricade, face)) => {
+                brain.block(Some(*barricade));
+                // stay a hair inside so the contact carries over to the next frame
+                let stop = face - enemy.hurtbox().x / 2. + 2.;
+                transform.translation.x = transform.translation.x.min(stop);
+            }
+            None => brain.block(None),
+        }
+    }
+}
+
+fn damage_barricades(
+    mut commands: Commands,
+    mut events: EventReader<TrapStruck>,
+    mut query: Query<(&mut Trap, &Transform, &mut Sprite)>,
+    mut particles: EventWriter<ParticleBurst>,
+    mut sounds: EventWriter<SoundEvent>,
+) {
+    for event in events.iter() {
+        let (mut trap, transform, mut sprite) = match query.get_mut(event.trap) {
+            Ok(trap) => trap,
+            Err(_) => continue,
+        };
+        if trap.health <= 0. {
+            continue;
+        }
+
+        trap.health -= event.amount;
+        sounds.send(SoundEvent(Sound::WallHit));
+
+        sprite.color = trap.color();
+
+        if trap.health <= 0. {
+            particles.send(ParticleBurst::new(ParticlePreset::WallDebris, transform.translation.truncate()));
+            commands.entity(event.trap).despawn();
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use bevy::{prelude::*, winit::WinitSettings};
 
-use crate::{player::Player, wall::Wall};
+use crate::{commons::AppState, economy::{Expense, Wallet}, patterns::{PatternBook, SelectedPattern}, player::{AimMode, CastMode, Player, Upgrades}, traps::{TrapKind, TrapPlacement}, wall::{Wall, WallModule, WallModules, WallRepair}};
 
 pub struct UiPlugin;
 
@@ -9,7 +9,9 @@ impl Plugin for UiPlugin {
         app.add_startup_system(spawn_hud)
             .add_startup_system(spawn_upgrade_buttons)
             .add_system(update_hud)
-            .add_system(click_button_system)
+            .add_system(update_pattern_button)
+            .add_system(update_repair_all_button)
+            .add_system_set(SystemSet::on_update(AppState::Playing).with_system(click_button_system))
             .insert_resource(WinitSettings::game());
     }
 }
@@ -27,8 +29,14 @@ fn spawn_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
     };
 
     let hud_text = Text::from_sections([
-        TextSection::new("Health: 200 \n", text_style.clone()),
+        TextSection::new("Wall: 200/200 \n", text_style.clone()),
+        TextSection::new("Wizard: 100/100 \n", text_style.clone()),
         TextSection::new("$0", text_style.clone()),
+        TextSection::new("Aim: Row (M) \n", text_style.clone()),
+        TextSection::new("Cast: Tap (C) \n", text_style.clone()),
+        TextSection::new("Pattern: Single (P)", text_style.clone()),
+        TextSection::new("", text_style.clone()),
+        TextSection::new("", text_style.clone()),
     ]);
 
     commands
@@ -43,31 +51,82 @@ fn spawn_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
         .insert(Hud);
 }
 
+#[allow(clippy::too_many_arguments)]
 fn update_hud(
     mut hud_query: Query<&mut Text, With<Hud>>,
     player_query: Query<&Player>,
     wall: Res<Wall>,
+    wall_modules: Res<WallModules>,
+    aim_mode: Res<AimMode>,
+    cast_mode: Res<CastMode>,
+    pattern_book: Res<PatternBook>,
+    selected_pattern: Res<SelectedPattern>,
+    trap_placement: Res<TrapPlacement>,
+    wallet: Res<Wallet>,
 ) {
     let player = player_query.single();
 
     let mut text = hud_query.single_mut();
-    text.sections[0].value = format!("Health: {} \n", wall.health());
-    text.sections[1].value = format!("${} \n", player.wealth());
+    text.sections[0].value = if wall.barrier() > 0. {
+        format!("Wall: {:.0}/{:.0} +{:.0} \n", wall.health(), wall.max_health(), wall.barrier())
+    } else {
+        format!("Wall: {:.0}/{:.0} \n", wall.health(), wall.max_health())
+    };
+    text.sections[1].value = format!("Wizard: {:.0}/{:.0} \n", player.health(), player.max_health());
+    text.sections[2].value = format!("${} \n", wallet.balance());
+    text.sections[3].value = format!("Aim: {} (M) \n", aim_mode.name());
+    text.sections[4].value = format!("Cast: {} (C) \n", cast_mode.name());
+    text.sections[5].value = format!("Pattern: {} (P)", pattern_book.get(**selected_pattern).name);
+    text.sections[6].value = match wall_modules.shockwave_cooldown() {
+        Some(cooldown) if cooldown > 0. => format!(" \nShockwave: {:.0}s", cooldown.ceil()),
+        Some(_) => " \nShockwave: Ready (F)".to_string(),
+        None => String::new(),
+    };
+    text.sections[7].value = match trap_placement.armed() {
+        Some(kind) => format!(" \nPlacing {} (right click cancels)", kind.name()),
+        None => String::new(),
+    };
 }
 
 #[derive(Component)]
 enum ButtonType {
     RepairWall,
+    RepairAll,
     UpgradeWall,
+    UpgradeCrit,
+    HealingPotion,
+    LearnPattern,
+    WallModule(WallModule),
+    Trap(TrapKind),
 }
 
+// The pattern button's label names whichever pattern is for sale next
+#[derive(Component)]
+struct PatternButtonText;
+
+// The bulk repair button's label shows what a full repair costs right now
+#[derive(Component)]
+struct RepairAllText;
+
 fn spawn_upgrade_buttons(mut commands: Commands, asset_server: Res<AssetServer>) {
     let font: Handle<Font> = asset_server.load("font.ttf");
 
-    let button_text = [
-        "Repair Wall $100",
-        "Upgrade Damage $800",
-        "Upgrade Wall $1000",
+    let buttons = [
+        ("Repair Wall $100", ButtonType::RepairWall),
+        ("", ButtonType::RepairAll),
+        ("Upgrade Wall $1000", ButtonType::UpgradeWall),
+        ("Sharpen Crits $600", ButtonType::UpgradeCrit),
+        ("Healing Potion $150", ButtonType::HealingPotion),
+        ("", ButtonType::LearnPattern),
+        ("Thorns $400", ButtonType::WallModule(WallModule::Thorns)),
+        ("Spike Traps $500", ButtonType::WallModule(WallModule::Spikes)),
+        ("Arcane Barrier $700", ButtonType::WallModule(WallModule::Barrier)),
+        ("Shockwave $1200", ButtonType::WallModule(WallModule::Shockwave)),
+        ("Wall Regen $600", ButtonType::WallModule(WallModule::Regen)),
+        ("Tar Pit $150", ButtonType::Trap(TrapKind::TarPit)),
+        ("Fire Rune $200", ButtonType::Trap(TrapKind::FireRune)),
+        ("Barricade $250", ButtonType::Trap(TrapKind::Barricade)),
+        ("Barrel $300", ButtonType::Trap(TrapKind::Barrel)),
     ];
 
     commands
@@ -75,74 +134,154 @@ fn spawn_upgrade_buttons(mut commands: Commands, asset_server: Res<AssetServer>)
             style: Style {
                 size: Size::new(Val::Percent(100.), Val::Percent(100.)),
                 justify_content: JustifyContent::Center,
+                flex_wrap: FlexWrap::Wrap,
+                align_content: AlignContent::FlexStart,
                 ..default()
             },
             background_color: Color::NONE.into(),
             ..default()
         })
         .with_children(|commands| {
-            for i in 0..2 {
-                let text = button_text[i];
-                let button_type = match i {
-                    0 => ButtonType::RepairWall,
-                    1 => ButtonType::UpgradeWall,
-                    _ => ButtonType::RepairWall,
-                };
-
+            for (text, button_type) in buttons {
                 commands
                     .spawn(ButtonBundle {
                         style: Style {
-                            size: Size::new(Val::Px(190.), Val::Px(30.)),
+                            size: Size::new(Val::Px(160.), Val::Px(30.)),
                             align_self: AlignSelf::FlexStart,
-                            margin: UiRect::all(Val::Percent(2.)),
+                            margin: UiRect::all(Val::Px(4.)),
                             justify_content: JustifyContent::Center,
                             ..default()
                         },
                         ..default()
                     })
                     .with_children(|parent| {
-                        parent.spawn(TextBundle::from_section(
+                        let mut text = parent.spawn(TextBundle::from_section(
                             text,
                             TextStyle {
                                 font: font.clone(),
-                                font_size: 20.,
+                                font_size: 16.,
                                 color: Color::BLACK,
                             },
                         ));
+
+                        match button_type {
+                            ButtonType::LearnPattern => {
+                                text.insert(PatternButtonText);
+                            }
+                            ButtonType::RepairAll => {
+                                text.insert(RepairAllText);
+                            }
+                            _ => {}
+                        }
                     })
                     .insert(button_type);
             }
         });
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn click_button_system(
     query: Query<(&Interaction, &ButtonType), (Changed<Interaction>, With<Button>)>,
     mut player_query: Query<&mut Player>,
     mut wall: ResMut<Wall>,
+    mut wall_modules: ResMut<WallModules>,
+    mut upgrades: ResMut<Upgrades>,
+    mut wallet: ResMut<Wallet>,
+    pattern_book: Res<PatternBook>,
+    mut trap_placement: ResMut<TrapPlacement>,
+    repair: Res<WallRepair>,
 ) {
     let mut player = player_query.single_mut();
 
+    // every purchase checks its own conditions first, so gold is only taken when it'll do something
     for (interaction, button_type) in query.iter() {
-        match *interaction {
-            Interaction::Clicked => {
-                match button_type {
-                    ButtonType::RepairWall => {
-                        let cost: usize = 100;
-                        if player.wealth() >= cost {
-                            player.spend(cost);
-                            wall.repair();
-                        }
+        if *interaction == Interaction::Clicked {
+            match button_type {
+                // no patching the wall while it's still being hit
+                ButtonType::RepairWall => {
+                    let repairable = wall.is_damaged() && !repair.under_attack();
+                    if repairable && wallet.try_spend(Expense::Repair, Wall::REPAIR_COST).is_ok() {
+                        wall.repair(Wall::REPAIR_AMOUNT);
                     }
-                    ButtonType::UpgradeWall => {
-                        let cost: usize = 1000;
-                        if player.wealth() >= cost {
-                            player.spend(cost);
-                            wall.upgrade_max_health();
-                        }
+                }
+                ButtonType::RepairAll => {
+                    let repairable = wall.is_damaged() && !repair.under_attack();
+                    if repairable && wallet.try_spend(Expense::Repair, wall.full_repair_cost()).is_ok() {
+                        let missing = wall.max_health() - wall.health();
+                        wall.repair(missing);
                     }
-                };
-            }
-            _ => {}
-        };
+                }
+                ButtonType::UpgradeWall => {
+                    if wallet.try_spend(Expense::Upgrade, 1000).is_ok() {
+                        wall.upgrade_max_health();
+                        upgrades.wall_max_health += 1;
+                    }
+                }
+                ButtonType::UpgradeCrit => {
+                    if wallet.try_spend(Expense::Upgrade, 600).is_ok() {
+                        upgrades.crit += 1;
+                    }
+                }
+                ButtonType::HealingPotion => {
+                    let hurt = player.health() < player.max_health();
+                    if hurt && wallet.try_spend(Expense::Potion, 150).is_ok() {
+                        player.heal(Player::POTION_HEAL);
+                    }
+                }
+                ButtonType::LearnPattern => {
+                    let cost = match pattern_book.next_unlock(&upgrades) {
+                        Some(pattern) => pattern.cost,
+                        None => continue,
+                    };
+                    if wallet.try_spend(Expense::Pattern, cost).is_ok() {
+                        upgrades.patterns += 1;
+                    }
+                }
+                ButtonType::WallModule(module) => {
+                    if !wall_modules.is_maxed(*module) && wallet.try_spend(Expense::WallModule, module.cost()).is_ok() {
+                        wall_modules.upgrade(*module, &mut wall);
+                    }
+                }
+                // paid for once it's actually put down in the field
+                ButtonType::Trap(kind) => {
+                    if wallet.can_afford(kind.cost()) {
+                        trap_placement.arm(*kind);
+                    }
+                }
+            };
+        }
+    }
+}
+
+fn update_pattern_button(
+    mut query: Query<&mut Text, With<PatternButtonText>>,
+    pattern_book: Res<PatternBook>,
+    upgrades: Res<Upgrades>,
+) {
+    let label = match pattern_book.next_unlock(&upgrades) {
+        Some(pattern) => format!("Learn {} ${}", pattern.name, pattern.cost),
+        None => "All Patterns Known".to_string(),
+    };
+
+    for mut text in query.iter_mut() {
+        if text.sections[0].value != label {
+            text.sections[0].value = label.clone();
+        }
+    }
+}
+
+fn update_repair_all_button(mut query: Query<&mut Text, With<RepairAllText>>, wall: Res<Wall>, repair: Res<WallRepair>) {
+    let label = if !wall.is_damaged() {
+        "Wall Intact".to_string()
+    } else if repair.under_attack() {
+        "Under Attack".to_string()
+    } else {
+        format!("Repair All ${}", wall.full_repair_cost())
+    };
+
+    for mut text in query.iter_mut() {
+        if text.sections[0].value != label {
+            text.sections[0].value = label.clone();
+        }
     }
 }
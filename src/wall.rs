@@ -1,28 +1,83 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, sprite::Anchor};
+use serde::{Deserialize, Serialize};
 use std::default::Default;
 
-use crate::commons::GameTextures;
+use crate::ai::Brain;
+use crate::commons::{AppState, GameTextures};
+use crate::damage::{Damage, DamageKind, DamageStyle, Hit, ResolveHits};
+use crate::enemy::{Elevation, Enemy, EnemyState};
+use crate::feedback::ScreenShake;
+use crate::particles::{ParticleBurst, ParticlePreset};
+use crate::player::Player;
+use crate::profile::Profile;
+use crate::sound::{Sound, SoundEvent};
 
 pub struct WallPlugin;
 
 impl Plugin for WallPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(spawn_wall);
+        app.add_event::<WallDamaged>()
+            .init_resource::<WallModules>()
+            .init_resource::<WallRepair>()
+            .add_startup_system(spawn_wall)
+            .add_startup_system(spawn_repair_bar)
+            .add_system_set(SystemSet::on_enter(AppState::Playing).with_system(reset_wall))
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(wall_fallen)
+                    .with_system(wall_sounds)
+                    .with_system(thorns.before(ResolveHits))
+                    .with_system(spike_traps.before(ResolveHits))
+                    .with_system(recharge_barrier)
+                    .with_system(shockwave)
+                    .with_system(track_attacks)
+                    .with_system(regenerate_wall.after(track_attacks))
+                    .with_system(channel_repair.after(track_attacks))
+                    .with_system(update_repair_bar.after(channel_repair)),
+            );
     }
 }
 
-#[derive(Resource)]
+pub struct WallDamaged {
+    pub amount: f32,
+    pub position: Vec2,
+    // the enemy striking the wall in melee, if it was one
+    pub attacker: Option<Entity>,
+}
+
+#[derive(Resource, Clone, Serialize, Deserialize)]
 pub struct Wall {
     health: f32,
     max_health: f32,
+    // the arcane barrier's charge, used up before any health
+    #[serde(default)]
+    barrier: f32,
 }
 
 impl Wall {
     pub const LEFT: f32 = 600.;
     pub const SIZE: f32 = 64.;
 
-    pub fn apply_damage(&mut self, damage: f32) {
-        self.health -= damage;
+    pub fn with_max_health(max_health: f32) -> Self {
+        Self {
+            health: max_health,
+            max_health,
+            barrier: 0.,
+        }
+    }
+
+    // Returns how much health the wall actually lost
+    pub fn apply_damage(&mut self, damage: f32) -> f32 {
+        let absorbed = damage.min(self.barrier);
+        self.barrier -= absorbed;
+
+        let lost = damage - absorbed;
+        self.health -= lost;
+        lost
+    }
+
+    pub fn barrier(&self) -> f32 {
+        self.barrier
     }
 
     pub fn health(&self) -> f32 {
@@ -33,14 +88,31 @@ impl Wall {
         self.max_health
     }
 
-    pub fn repair(&mut self) {
-        let repair_amount: f32 = 10.;
+    pub fn set_health(&mut self, health: f32) {
+        self.health = health.clamp(0., self.max_health);
+    }
 
-        if self.health() + repair_amount > self.max_health() {
-            self.health = self.max_health;
-        } else {
-            self.health += repair_amount;
-        }
+    pub fn is_critical(&self) -> bool {
+        self.health <= self.max_health * 0.25
+    }
+
+    // what a single repair button press restores and costs
+    pub const REPAIR_AMOUNT: f32 = 10.;
+    pub const REPAIR_COST: usize = 100;
+    // repairing all the way to full is cheaper per point
+    const BULK_DISCOUNT: f32 = 0.75;
+
+    pub fn repair(&mut self, amount: f32) {
+        self.health = (self.health + amount).min(self.max_health);
+    }
+
+    pub fn is_damaged(&self) -> bool {
+        self.health < self.max_health
+    }
+
+    pub fn full_repair_cost(&self) -> usize {
+        let per_point = Self::REPAIR_COST as f32 / Self::REPAIR_AMOUNT;
+        ((self.max_health - self.health).max(0.) * per_point * Self::BULK_DISCOUNT).ceil() as usize
     }
 
     pub fn upgrade_max_health(&mut self) {
@@ -53,10 +125,7 @@ impl Wall {
 
 impl Default for Wall {
     fn default() -> Self {
-        Self {
-            health: 200.,
-            max_health: 200.,
-        }
+        Self::with_max_health(200.)
     }
 }
 
@@ -79,3 +148,382 @@ fn spawn_wall(mut commands: Commands, game_textures: Res<GameTextures>) {
     }
 }
 
+
+fn reset_wall(mut commands: Commands, profile: Res<Profile>) {
+    commands.insert_resource(Wall::with_max_health(profile.wall_max_health()));
+    commands.insert_resource(WallModules::default());
+    commands.insert_resource(WallRepair::default());
+}
+
+fn wall_fallen(wall: Res<Wall>, mut state: ResMut<State<AppState>>) {
+    if wall.health() <= 0. {
+        let _ = state.set(AppState::GameOver);
+    }
+}
+
+// Every blow the wall takes is heard, whoever or whatever landed it
+fn wall_sounds(
+    mut events: EventReader<WallDamaged>,
+    wall: Res<Wall>,
+    mut was_critical: Local<bool>,
+    mut sounds: EventWriter<SoundEvent>,
+) {
+    if events.iter().count() > 0 {
+        sounds.send(SoundEvent(Sound::WallHit));
+        if !*was_critical && wall.is_critical() {
+            sounds.send(SoundEvent(Sound::WallCritical));
+        }
+    }
+
+    *was_critical = wall.is_critical();
+}
+
+#[derive(Clone, Copy)]
+pub enum WallModule {
+    Thorns,
+    Spikes,
+    Barrier,
+    Shockwave,
+    Regen,
+}
+
+impl WallModule {
+    pub fn cost(&self) -> usize {
+        match self {
+            WallModule::Thorns => 400,
+            WallModule::Spikes => 500,
+            WallModule::Barrier => 700,
+            WallModule::Shockwave => 1200,
+            WallModule::Regen => 600,
+        }
+    }
+
+    fn max_level(&self) -> u32 {
+        match self {
+            WallModule::Shockwave => 1,
+            _ => 3,
+        }
+    }
+}
+
+// Defenses bought for the wall, each level making the module stronger
+// Timers aren't saved, they start over from the defaults on load
+#[derive(Resource, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WallModules {
+    thorns: u32,
+    spikes: u32,
+    barrier: u32,
+    shockwave: u32,
+    regen: u32,
+    #[serde(skip)]
+    spike_timer: Timer,
+    #[serde(skip)]
+    barrier_recharge: Timer,
+    // seconds until the shockwave can be used again
+    #[serde(skip)]
+    shockwave_cooldown: f32,
+}
+
+impl WallModules {
+    // fraction of each melee blow sent back per level
+    const THORNS_REFLECT: f32 = 0.25;
+    const SPIKE_DAMAGE: f32 = 6.;
+    const SPIKE_INTERVAL: f32 = 1.;
+    const BARRIER_PER_LEVEL: f32 = 30.;
+    const BARRIER_RECHARGE: f32 = 8.;
+    const SHOCKWAVE_COOLDOWN: f32 = 12.;
+    const SHOCKWAVE_FORCE: f32 = 1200.;
+    // health per second per level
+    const REGEN_PER_LEVEL: f32 = 1.;
+
+    fn level(&self, module: WallModule) -> u32 {
+        match module {
+            WallModule::Thorns => self.thorns,
+            WallModule::Spikes => self.spikes,
+            WallModule::Barrier => self.barrier,
+            WallModule::Shockwave => self.shockwave,
+            WallModule::Regen => self.regen,
+        }
+    }
+
+    pub fn is_maxed(&self, module: WallModule) -> bool {
+        self.level(module) >= module.max_level()
+    }
+
+    pub fn upgrade(&mut self, module: WallModule, wall: &mut Wall) {
+        if self.is_maxed(module) {
+            return;
+        }
+
+        match module {
+            WallModule::Thorns => self.thorns += 1,
+            WallModule::Spikes => self.spikes += 1,
+            WallModule::Barrier => {
+                self.barrier += 1;
+                wall.barrier = self.barrier_capacity();
+            }
+            WallModule::Shockwave => self.shockwave += 1,
+            WallModule::Regen => self.regen += 1,
+        }
+    }
+
+    fn barrier_capacity(&self) -> f32 {
+        Self::BARRIER_PER_LEVEL * self.barrier as f32
+    }
+
+    // None without the module, zero when it's ready
+    pub fn shockwave_cooldown(&self) -> Option<f32> {
+        (self.shockwave > 0).then_some(self.shockwave_cooldown.max(0.))
+    }
+}
+
+impl Default for WallModules {
+    fn default() -> Self {
+        Self {
+            thorns: 0,
+            spikes: 0,
+            barrier: 0,
+            shockwave: 0,
+            regen: 0,
+            spike_timer: Timer::from_seconds(Self::SPIKE_INTERVAL, TimerMode::Repeating),
+            barrier_recharge: Timer::from_seconds(Self::BARRIER_RECHARGE, TimerMode::Once),
+            shockwave_cooldown: 0.,
+        }
+    }
+}
+
+// Whether an enemy is standing at the wall's foot, where the spikes are
+fn in_attack_zone(x: f32) -> bool {
+    (Wall::LEFT - Wall::SIZE..Wall::LEFT).contains(&x)
+}
+
+fn thorns(mut events: EventReader<WallDamaged>, modules: Res<WallModules>, mut hits: EventWriter<Hit>) {
+    if modules.thorns == 0 {
+        events.clear();
+        return;
+    }
+
+    for event in events.iter() {
+        if let Some(attacker) = event.attacker {
+            let reflected = event.amount * WallModules::THORNS_REFLECT * modules.thorns as f32;
+            hits.send(Hit {
+                target: attacker,
+                position: event.position,
+                damage: Damage::new(reflected, DamageKind::Physical),
+                spell: None,
+                style: DamageStyle::Normal,
+            });
+        }
+    }
+}
+
+fn spike_traps(
+    mut modules: ResMut<WallModules>,
+    query: Query<(Entity, &EnemyState, Option<&Elevation>, &Transform), With<Enemy>>,
+    mut hits: EventWriter<Hit>,
+    mut particles: EventWriter<ParticleBurst>,
+    time: Res<Time>,
+) {
+    if modules.spikes == 0 || !modules.spike_timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let damage = WallModules::SPIKE_DAMAGE * modules.spikes as f32;
+    for (entity, state, elevation, transform) in query.iter() {
+        let grounded = elevation.copied().unwrap_or_default() == Elevation::Ground;
+        if *state == EnemyState::Death || !grounded || !in_attack_zone(transform.translation.x) {
+            continue;
+        }
+
+        let position = transform.translation.truncate();
+        hits.send(Hit {
+            target: entity,
+            position,
+            damage: Damage::new(damage, DamageKind::Physical),
+            spell: None,
+            style: DamageStyle::Status,
+        });
+        particles.send(ParticleBurst::new(ParticlePreset::ImpactSparks, position));
+    }
+}
+
+// Once the barrier breaks it stays down for a while, then comes back at full strength
+fn recharge_barrier(mut modules: ResMut<WallModules>, mut wall: ResMut<Wall>, time: Res<Time>) {
+    if modules.barrier == 0 || wall.barrier > 0. {
+        return;
+    }
+
+    if modules.barrier_recharge.tick(time.delta()).just_finished() {
+        wall.barrier = modules.barrier_capacity();
+        modules.barrier_recharge.reset();
+    }
+}
+
+// Blast everything clawing at the wall back into the field
+fn shockwave(
+    keyboard: Res<Input<KeyCode>>,
+    mut modules: ResMut<WallModules>,
+    mut query: Query<(&EnemyState, &mut Brain, &Transform)>,
+    mut shake: ResMut<ScreenShake>,
+    mut particles: EventWriter<ParticleBurst>,
+    mut sounds: EventWriter<SoundEvent>,
+    time: Res<Time>,
+) {
+    modules.shockwave_cooldown -= time.delta_seconds();
+
+    if modules.shockwave == 0 || modules.shockwave_cooldown > 0. || !keyboard.just_pressed(KeyCode::F) {
+        return;
+    }
+
+    modules.shockwave_cooldown = WallModules::SHOCKWAVE_COOLDOWN;
+    shake.add_trauma(0.5);
+    sounds.send(SoundEvent(Sound::WallHit));
+
+    for (state, mut brain, transform) in query.iter_mut() {
+        if *state == EnemyState::Attack && in_attack_zone(transform.translation.x) {
+            brain.knock_back(Vec2::new(-WallModules::SHOCKWAVE_FORCE, 0.));
+            particles.send(ParticleBurst::new(ParticlePreset::WallDebris, transform.translation.truncate()));
+        }
+    }
+}
+
+// Repairs only happen once the wall has been left alone for a moment
+#[derive(Resource)]
+pub struct WallRepair {
+    since_attacked: f32,
+    // whether the wizard is holding the repair key this frame
+    channeling: bool,
+    // how far through the current channel, from 0 to 1
+    progress: f32,
+}
+
+impl WallRepair {
+    const LOCKOUT: f32 = 3.;
+    const CHANNEL_TIME: f32 = 2.;
+    const CHANNEL_AMOUNT: f32 = 15.;
+
+    pub fn under_attack(&self) -> bool {
+        self.since_attacked < Self::LOCKOUT
+    }
+
+    // The wizard can't move or cast while channeling
+    pub fn channeling(&self) -> bool {
+        self.channeling
+    }
+}
+
+impl Default for WallRepair {
+    fn default() -> Self {
+        Self {
+            since_attacked: Self::LOCKOUT,
+            channeling: false,
+            progress: 0.,
+        }
+    }
+}
+
+fn track_attacks(mut events: EventReader<WallDamaged>, mut repair: ResMut<WallRepair>, time: Res<Time>) {
+    if events.iter().count() > 0 {
+        repair.since_attacked = 0.;
+    } else {
+        repair.since_attacked += time.delta_seconds();
+    }
+}
+
+fn regenerate_wall(modules: Res<WallModules>, repair: Res<WallRepair>, mut wall: ResMut<Wall>, time: Res<Time>) {
+    if modules.regen == 0 || repair.under_attack() || !wall.is_damaged() {
+        return;
+    }
+
+    wall.repair(WallModules::REGEN_PER_LEVEL * modules.regen as f32 * time.delta_seconds());
+}
+
+// Hold R to stand still and patch up the segment in front of the wizard.
+// An attack pauses the channel, letting go throws away the progress.
+fn channel_repair(
+    keyboard: Res<Input<KeyCode>>,
+    player_query: Query<&Player>,
+    mut repair: ResMut<WallRepair>,
+    mut wall: ResMut<Wall>,
+    time: Res<Time>,
+) {
+    let alive = player_query.get_single().is_ok_and(|player| player.health() > 0.);
+    repair.channeling = alive && keyboard.pressed(KeyCode::R) && wall.is_damaged();
+
+    if !repair.channeling {
+        repair.progress = 0.;
+        return;
+    }
+    if repair.under_attack() {
+        return;
+    }
+
+    repair.progress += time.delta_seconds() / WallRepair::CHANNEL_TIME;
+    if repair.progress >= 1. {
+        repair.progress = 0.;
+        wall.repair(WallRepair::CHANNEL_AMOUNT);
+    }
+}
+
+// Systems for the bar over the segment being repaired
+const REPAIR_BAR_SIZE: Vec2 = Vec2::new(60., 8.);
+
+#[derive(Component)]
+struct RepairBar;
+
+#[derive(Component)]
+struct RepairBarFill;
+
+fn spawn_repair_bar(mut commands: Commands) {
+    commands
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgba(0., 0., 0., 0.6),
+                custom_size: Some(REPAIR_BAR_SIZE),
+                ..Default::default()
+            },
+            visibility: Visibility { is_visible: false },
+            transform: Transform::from_xyz(Wall::LEFT, 0., 50.),
+            ..Default::default()
+        })
+        .insert(RepairBar)
+        .with_children(|parent| {
+            parent
+                .spawn(SpriteBundle {
+                    sprite: Sprite {
+                        custom_size: Some(Vec2::new(0., REPAIR_BAR_SIZE.y)),
+                        anchor: Anchor::CenterLeft,
+                        ..Default::default()
+                    },
+                    transform: Transform::from_xyz(-REPAIR_BAR_SIZE.x / 2., 0., 1.),
+                    ..Default::default()
+                })
+                .insert(RepairBarFill);
+        });
+}
+
+#[allow(clippy::type_complexity)]
+fn update_repair_bar(
+    repair: Res<WallRepair>,
+    player_query: Query<&Transform, (With<Player>, Without<RepairBar>)>,
+    mut bar_query: Query<(&mut Transform, &mut Visibility), With<RepairBar>>,
+    mut fill_query: Query<&mut Sprite, With<RepairBarFill>>,
+) {
+    let (mut bar_transform, mut visibility) = bar_query.single_mut();
+    visibility.is_visible = repair.channeling;
+
+    if let Ok(player_transform) = player_query.get_single() {
+        bar_transform.translation.y = player_transform.translation.y + Wall::SIZE / 2. + 10.;
+    }
+
+    // red while an attack is holding the repair up
+    let mut fill = fill_query.single_mut();
+    fill.color = if repair.under_attack() {
+        Color::rgb(0.9, 0.2, 0.2)
+    } else {
+        Color::rgb(0.3, 0.9, 0.4)
+    };
+    let width = if repair.under_attack() { REPAIR_BAR_SIZE.x } else { REPAIR_BAR_SIZE.x * repair.progress };
+    fill.custom_size = Some(Vec2::new(width, REPAIR_BAR_SIZE.y));
+}